@@ -1,6 +1,8 @@
 use crate::expr::Expr;
+use crate::stmt::{FunctionDeclaration, Stmt};
 use crate::token::TokenType;
 
+// Renders syntax trees as parenthesized prefix expressions, for `--dump=ast`.
 pub trait ASTPrintable {
     fn print(&self) -> String;
 }
@@ -8,6 +10,7 @@ pub trait ASTPrintable {
 impl ASTPrintable for Expr {
     fn print(&self) -> String {
         match self {
+            Expr::Assign { name, value, .. } => format!("(= {} {})", name.lexeme(), value.print()),
             Expr::Binary {
                 left,
                 operator,
@@ -15,14 +18,63 @@ impl ASTPrintable for Expr {
             } => {
                 format!("({} {} {})", operator.lexeme(), left.print(), right.print())
             }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let arguments: String = arguments
+                    .iter()
+                    .map(|a| format!(" {}", a.print()))
+                    .collect();
+                format!("(call {}{})", callee.print(), arguments)
+            }
+            Expr::Get { object, name } => format!("(. {} {})", object.print(), name.lexeme()),
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => format!(
+                "(= (. {} {}) {})",
+                object.print(),
+                name.lexeme(),
+                value.print()
+            ),
+            Expr::Super { method, .. } => format!("(super {})", method.lexeme()),
+            Expr::This { .. } => String::from("this"),
             Expr::Grouping { expression } => format!("(group {})", expression.print()),
+            Expr::Index { object, index, .. } => {
+                format!("(index {} {})", object.print(), index.print())
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => format!(
+                "(= (index {} {}) {})",
+                object.print(),
+                index.print(),
+                value.print()
+            ),
+            Expr::List { elements, .. } => {
+                let elements: String = elements.iter().map(|e| format!(" {}", e.print())).collect();
+                format!("(list{})", elements)
+            }
+            Expr::Map { entries, .. } => {
+                let entries: String = entries
+                    .iter()
+                    .map(|(key, value)| format!(" ({} {})", key.print(), value.print()))
+                    .collect();
+                format!("(map{})", entries)
+            }
             Expr::Literal { value } => match value.type_info() {
                 TokenType::Number(num) => format!("{}", num),
                 TokenType::Str(text) => format!("\"{}\"", text),
-                TokenType::Identifier(ident) => format!("{}", ident),
+                TokenType::Identifier(ident) => ident.to_string(),
+                TokenType::True => String::from("true"),
+                TokenType::False => String::from("false"),
                 TokenType::Nil => String::from("nil"),
                 TokenType::EndOfFile => String::from("EndOfFile"),
-                _ => format!("Error: Unknown literal type!"),
+                _ => String::from("Error: Unknown literal type!"),
             },
             Expr::Conditional {
                 condition,
@@ -36,8 +88,87 @@ impl ASTPrintable for Expr {
                     alternative.print()
                 )
             }
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                format!("({} {} {})", operator.lexeme(), left.print(), right.print())
+            }
             Expr::Unary { operator, right } => format!("({} {})", operator.lexeme(), right.print()),
-            Expr::Variable { token } => format!("var {}", token.lexeme()),
+            Expr::Variable { token, .. } => format!("var {}", token.lexeme()),
         }
     }
 }
+
+impl ASTPrintable for Stmt {
+    fn print(&self) -> String {
+        match self {
+            Stmt::Block { statements, .. } => format!("(block{})", print_all(statements)),
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => {
+                let superclass = match superclass {
+                    Some(superclass) => format!(" < {}", superclass.print()),
+                    None => String::new(),
+                };
+                let methods: String = methods
+                    .iter()
+                    .map(|method| format!(" {}", print_function(method)))
+                    .collect();
+                format!("(class {}{}{})", name.lexeme(), superclass, methods)
+            }
+            Stmt::Expression { expression, .. } => format!("(; {})", expression.print()),
+            Stmt::Function(declaration) => print_function(declaration),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => match else_branch {
+                Some(else_branch) => format!(
+                    "(if {} {} else {})",
+                    condition.print(),
+                    then_branch.print(),
+                    else_branch.print()
+                ),
+                None => format!("(if {} {})", condition.print(), then_branch.print()),
+            },
+            Stmt::Print { expression, .. } => format!("(print {})", expression.print()),
+            Stmt::Return { value, .. } => match value {
+                Some(value) => format!("(return {})", value.print()),
+                None => String::from("(return)"),
+            },
+            Stmt::Var {
+                name, initializer, ..
+            } => match initializer {
+                Some(initializer) => format!("(var {} {})", name.lexeme(), initializer.print()),
+                None => format!("(var {})", name.lexeme()),
+            },
+            Stmt::While {
+                condition, body, ..
+            } => format!("(while {} {})", condition.print(), body.print()),
+        }
+    }
+}
+
+fn print_function(declaration: &FunctionDeclaration) -> String {
+    let params: Vec<&str> = declaration.params.iter().map(|p| p.lexeme()).collect();
+    format!(
+        "(fun {} ({}){})",
+        declaration.name.lexeme(),
+        params.join(" "),
+        print_all(&declaration.body)
+    )
+}
+
+// each statement preceded by a space, so it can follow a keyword directly
+fn print_all(statements: &[Stmt]) -> String {
+    statements
+        .iter()
+        .map(|statement| format!(" {}", statement.print()))
+        .collect()
+}
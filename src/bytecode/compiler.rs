@@ -0,0 +1,718 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    bytecode::{
+        chunk::{Chunk, OpCode},
+        heap::{FunctionProto, Heap, ObjRef, Object},
+        value::Value,
+    },
+    errors::{DiagnosticKind, Diagnostics},
+    expr::Expr,
+    stmt::{FunctionDeclaration, Stmt},
+    symbol::Symbol,
+    token::{Span, Token, TokenType},
+};
+
+const MAX_LOCALS: usize = 256;
+const MAX_UPVALUES: usize = 256;
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+struct Local {
+    name: Symbol,
+    depth: Option<usize>, // None until the variable's initializer has been compiled
+    is_captured: bool,    // closed over by an inner function, so it must be hoisted off the stack
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct UpvalueRef {
+    index: u8,
+    is_local: bool, // captures a local of the enclosing function rather than one of its upvalues
+}
+
+// Everything the compiler tracks for the function whose body it is currently emitting.
+struct FunctionState {
+    kind: FunctionKind,
+    name: Option<String>,
+    arity: usize,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+    identifiers: HashMap<ObjRef, u16>, // constant indexes of names already in the pool
+}
+
+impl FunctionState {
+    fn new(kind: FunctionKind, name: Option<String>) -> Self {
+        // slot zero holds the callee, or the receiver inside methods
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            FunctionKind::Script | FunctionKind::Function => "",
+        };
+
+        Self {
+            kind,
+            name,
+            arity: 0,
+            chunk: Chunk::new(),
+            locals: vec![Local {
+                name: Symbol::intern(receiver),
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: vec![],
+            scope_depth: 0,
+            identifiers: HashMap::new(),
+        }
+    }
+}
+
+// Where a variable lives, and so which instructions read and write it.
+enum Variable {
+    Local(u8),
+    Upvalue(u8),
+    Global(u16),
+}
+
+// Compiles a resolved program into the body of a top-level script function.
+// Strings and nested functions are allocated on `heap`; limits the bytecode
+// format can't express are reported to `diagnostics`. Every function keeps
+// `source`, the code the statements were parsed from.
+pub fn compile(
+    statements: &[Stmt],
+    source: &Rc<str>,
+    heap: &mut Heap,
+    diagnostics: &mut Diagnostics,
+) -> Rc<FunctionProto> {
+    let mut compiler = Compiler {
+        heap,
+        states: vec![FunctionState::new(FunctionKind::Script, None)],
+        diagnostics,
+        source,
+    };
+
+    // a trailing expression is the script's result, like the tree-walker's
+    let (body, result) = match statements.split_last() {
+        Some((Stmt::Expression { expression, span }, body)) => (body, Some((expression, *span))),
+        _ => (statements, None),
+    };
+    for statement in body {
+        compiler.statement(statement);
+    }
+
+    match result {
+        Some((expression, span)) => {
+            compiler.expression(expression);
+            compiler.emit_op(OpCode::Return, span);
+        }
+        None => {
+            let end = statements.last().map_or(Span::default(), Stmt::span);
+            compiler.emit_return(end);
+        }
+    }
+    let state = compiler.states.pop().expect("script state");
+    Rc::new(finish(state, source))
+}
+
+fn finish(state: FunctionState, source: &Rc<str>) -> FunctionProto {
+    FunctionProto {
+        name: state.name,
+        arity: state.arity,
+        upvalue_count: state.upvalues.len(),
+        chunk: state.chunk,
+        source: Rc::clone(source),
+    }
+}
+
+struct Compiler<'a> {
+    heap: &'a mut Heap,
+    states: Vec<FunctionState>, // innermost function last
+    diagnostics: &'a mut Diagnostics,
+    source: &'a Rc<str>,
+}
+
+impl Compiler<'_> {
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block { statements, span } => {
+                self.begin_scope();
+                for statement in statements {
+                    self.statement(statement);
+                }
+                self.end_scope(*span);
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => self.class(name, superclass.as_ref(), methods),
+            Stmt::Expression { expression, span } => {
+                self.expression(expression);
+                self.emit_op(OpCode::Pop, *span);
+            }
+            Stmt::Function(declaration) => {
+                let global = self.declare_variable(&declaration.name);
+                // a function may refer to itself, so it is usable before its body is compiled
+                self.mark_initialized();
+                self.function(declaration, FunctionKind::Function);
+                self.define_variable(global, declaration.name.span());
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                span,
+            } => {
+                self.expression(condition);
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse, *span);
+                self.emit_op(OpCode::Pop, *span);
+                self.statement(then_branch);
+
+                let else_jump = self.emit_jump(OpCode::Jump, *span);
+                self.patch_jump(then_jump, *span);
+                self.emit_op(OpCode::Pop, *span);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+                self.patch_jump(else_jump, *span);
+            }
+            Stmt::Print { expression, span } => {
+                self.expression(expression);
+                self.emit_op(OpCode::Print, *span);
+            }
+            Stmt::Return { value, span, .. } => {
+                if self.state().kind == FunctionKind::Initializer {
+                    // the resolver rejects `return value;` in init, so this always returns `this`
+                    self.emit_return(*span);
+                    return;
+                }
+                match value {
+                    Some(value) => self.expression(value),
+                    None => self.emit_op(OpCode::Nil, *span),
+                }
+                self.emit_op(OpCode::Return, *span);
+            }
+            Stmt::Var {
+                name,
+                initializer,
+                span,
+            } => {
+                let global = self.declare_variable(name);
+                match initializer {
+                    Some(initializer) => self.expression(initializer),
+                    None => self.emit_op(OpCode::Nil, *span),
+                }
+                self.define_variable(global, name.span());
+            }
+            Stmt::While {
+                condition,
+                body,
+                span,
+            } => {
+                let loop_start = self.chunk().code.len();
+                self.expression(condition);
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse, *span);
+                self.emit_op(OpCode::Pop, *span);
+                self.statement(body);
+                self.emit_loop(loop_start, *span);
+                self.patch_jump(exit_jump, *span);
+                self.emit_op(OpCode::Pop, *span);
+            }
+        }
+    }
+
+    fn class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Rc<FunctionDeclaration>],
+    ) {
+        let span = name.span();
+        let name_constant = self.identifier_constant(name.symbol(), span);
+        let global = self.declare_variable(name);
+        self.emit_op(OpCode::Class, span);
+        self.chunk().write_u16(name_constant, span);
+        self.define_variable(global, span);
+
+        // the superclass is kept in a local named `super` that the methods capture
+        if let Some(superclass) = superclass {
+            self.expression(superclass);
+            self.begin_scope();
+            self.add_local(&Symbol::intern("super"), superclass.span());
+            self.mark_initialized();
+
+            self.named_variable(name.symbol(), span, None);
+            self.emit_op(OpCode::Inherit, superclass.span());
+        }
+
+        self.named_variable(name.symbol(), span, None);
+        for method in methods {
+            let method_name = self.identifier_constant(method.name.symbol(), method.name.span());
+            let kind = if method.name.lexeme() == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            self.function(method, kind);
+            self.emit_op(OpCode::Method, method.name.span());
+            self.chunk().write_u16(method_name, method.name.span());
+        }
+        self.emit_op(OpCode::Pop, span);
+
+        if superclass.is_some() {
+            self.end_scope(span);
+        }
+    }
+
+    // compiles the body into its own function and emits the closure that captures its upvalues
+    fn function(&mut self, declaration: &FunctionDeclaration, kind: FunctionKind) {
+        self.states.push(FunctionState::new(
+            kind,
+            Some(String::from(declaration.name.lexeme())),
+        ));
+        self.begin_scope();
+
+        for param in &declaration.params {
+            self.state_mut().arity += 1;
+            self.add_local(param.symbol(), param.span());
+            self.mark_initialized();
+        }
+        for statement in &declaration.body {
+            self.statement(statement);
+        }
+        self.emit_return(declaration.span);
+
+        let state = self.states.pop().expect("function state");
+        let upvalues = state.upvalues.clone();
+        let function = Rc::new(finish(state, self.source));
+        let function = self.heap.alloc(Object::Function(function));
+
+        let span = declaration.name.span();
+        let constant = self.make_constant(Value::Obj(function), span);
+        self.emit_op(OpCode::Closure, span);
+        self.chunk().write_u16(constant, span);
+        for upvalue in upvalues {
+            self.chunk().write(u8::from(upvalue.is_local), span);
+            self.chunk().write(upvalue.index, span);
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                self.named_variable(name.symbol(), name.span(), Some(value))
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => self.binary(left, operator, right),
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                self.emit_op(OpCode::Call, paren.span());
+                // the parser caps argument lists at 255
+                self.chunk().write(arguments.len() as u8, paren.span());
+            }
+            Expr::Get { object, name } => {
+                self.expression(object);
+                let constant = self.identifier_constant(name.symbol(), name.span());
+                self.emit_op(OpCode::GetProperty, name.span());
+                self.chunk().write_u16(constant, name.span());
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                self.expression(object);
+                self.expression(value);
+                let constant = self.identifier_constant(name.symbol(), name.span());
+                self.emit_op(OpCode::SetProperty, name.span());
+                self.chunk().write_u16(constant, name.span());
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                self.expression(object);
+                self.expression(index);
+                self.emit_op(OpCode::GetIndex, bracket.span());
+            }
+            Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                self.expression(object);
+                self.expression(index);
+                self.expression(value);
+                self.emit_op(OpCode::SetIndex, bracket.span());
+            }
+            Expr::List { elements, span } => {
+                for element in elements {
+                    self.expression(element);
+                }
+                let Ok(length) = u16::try_from(elements.len()) else {
+                    self.error(*span, "Too many elements in one list literal.");
+                    return;
+                };
+                self.emit_op(OpCode::BuildList, *span);
+                self.chunk().write_u16(length, *span);
+            }
+            // entries are added one at a time so a bad key is reported where it is
+            Expr::Map { entries, span } => {
+                self.emit_op(OpCode::NewMap, *span);
+                for (key, value) in entries {
+                    self.expression(key);
+                    self.expression(value);
+                    self.emit_op(OpCode::MapEntry, key.span());
+                }
+            }
+            Expr::Super {
+                keyword, method, ..
+            } => {
+                self.named_variable(&Symbol::intern("this"), keyword.span(), None);
+                self.named_variable(&Symbol::intern("super"), keyword.span(), None);
+                let constant = self.identifier_constant(method.symbol(), method.span());
+                self.emit_op(OpCode::GetSuper, method.span());
+                self.chunk().write_u16(constant, method.span());
+            }
+            Expr::This { keyword, .. } => {
+                self.named_variable(&Symbol::intern("this"), keyword.span(), None)
+            }
+            Expr::Conditional {
+                condition,
+                consequent,
+                alternative,
+            } => {
+                let span = expr.span();
+                self.expression(condition);
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+                self.emit_op(OpCode::Pop, span);
+                self.expression(consequent);
+                let end_jump = self.emit_jump(OpCode::Jump, span);
+                self.patch_jump(else_jump, span);
+                self.emit_op(OpCode::Pop, span);
+                self.expression(alternative);
+                self.patch_jump(end_jump, span);
+            }
+            Expr::Grouping { expression } => self.expression(expression),
+            Expr::Literal { value } => self.literal(value),
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                let span = operator.span();
+                self.expression(left);
+                if let TokenType::Or = operator.type_info() {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+                    let end_jump = self.emit_jump(OpCode::Jump, span);
+                    self.patch_jump(else_jump, span);
+                    self.emit_op(OpCode::Pop, span);
+                    self.expression(right);
+                    self.patch_jump(end_jump, span);
+                } else {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse, span);
+                    self.emit_op(OpCode::Pop, span);
+                    self.expression(right);
+                    self.patch_jump(end_jump, span);
+                }
+            }
+            Expr::Unary { operator, right } => {
+                self.expression(right);
+                match operator.type_info() {
+                    TokenType::Bang => self.emit_op(OpCode::Not, operator.span()),
+                    _ => self.emit_op(OpCode::Negate, operator.span()),
+                }
+            }
+            Expr::Variable { token, .. } => self.named_variable(token.symbol(), token.span(), None),
+        }
+    }
+
+    fn binary(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        let span = operator.span();
+        self.expression(left);
+
+        // the comma operator evaluates and discards its left side
+        if let TokenType::Comma = operator.type_info() {
+            self.emit_op(OpCode::Pop, span);
+            self.expression(right);
+            return;
+        }
+
+        self.expression(right);
+        let op = match operator.type_info() {
+            TokenType::EqualEqual => OpCode::Equal,
+            TokenType::BangEqual => OpCode::NotEqual,
+            TokenType::Greater => OpCode::Greater,
+            TokenType::GreaterEqual => OpCode::GreaterEqual,
+            TokenType::Less => OpCode::Less,
+            TokenType::LessEqual => OpCode::LessEqual,
+            TokenType::Plus => OpCode::Add,
+            TokenType::Minus => OpCode::Subtract,
+            TokenType::Star => OpCode::Multiply,
+            _ => OpCode::Divide,
+        };
+        self.emit_op(op, span);
+    }
+
+    fn literal(&mut self, token: &Token) {
+        let span = token.span();
+        match token.type_info() {
+            TokenType::Number(n) => self.emit_constant(Value::Number(*n), span),
+            TokenType::Str(s) => {
+                let string = self.heap.intern(s.clone());
+                self.emit_constant(Value::Obj(string), span);
+            }
+            TokenType::True => self.emit_op(OpCode::True, span),
+            TokenType::False => self.emit_op(OpCode::False, span),
+            _ => self.emit_op(OpCode::Nil, span),
+        }
+    }
+
+    // loads the variable, or stores `assign` into it when given
+    fn named_variable(&mut self, name: &Symbol, span: Span, assign: Option<&Expr>) {
+        let innermost = self.states.len() - 1;
+        let variable = if let Some(slot) = self.resolve_local(innermost, name) {
+            Variable::Local(slot)
+        } else if let Some(index) = self.resolve_upvalue(innermost, name, span) {
+            Variable::Upvalue(index)
+        } else {
+            Variable::Global(self.identifier_constant(name, span))
+        };
+
+        if let Some(value) = assign {
+            self.expression(value);
+        }
+
+        match (variable, assign.is_some()) {
+            (Variable::Local(slot), false) => self.emit_with_byte(OpCode::GetLocal, slot, span),
+            (Variable::Local(slot), true) => self.emit_with_byte(OpCode::SetLocal, slot, span),
+            (Variable::Upvalue(index), false) => {
+                self.emit_with_byte(OpCode::GetUpvalue, index, span)
+            }
+            (Variable::Upvalue(index), true) => {
+                self.emit_with_byte(OpCode::SetUpvalue, index, span)
+            }
+            (Variable::Global(name), assign) => {
+                let op = if assign {
+                    OpCode::SetGlobal
+                } else {
+                    OpCode::GetGlobal
+                };
+                self.emit_op(op, span);
+                self.chunk().write_u16(name, span);
+            }
+        }
+    }
+
+    fn resolve_local(&self, state: usize, name: &Symbol) -> Option<u8> {
+        self.states[state]
+            .locals
+            .iter()
+            .rposition(|local| local.name == *name)
+            .map(|slot| slot as u8)
+    }
+
+    // looks for the name in each enclosing function, threading an upvalue through every level
+    fn resolve_upvalue(&mut self, state: usize, name: &Symbol, span: Span) -> Option<u8> {
+        if state == 0 {
+            return None;
+        }
+
+        if let Some(slot) = self.resolve_local(state - 1, name) {
+            self.states[state - 1].locals[slot as usize].is_captured = true;
+            return Some(self.add_upvalue(state, slot, true, span));
+        }
+
+        let index = self.resolve_upvalue(state - 1, name, span)?;
+        Some(self.add_upvalue(state, index, false, span))
+    }
+
+    fn add_upvalue(&mut self, state: usize, index: u8, is_local: bool, span: Span) -> u8 {
+        let upvalue = UpvalueRef { index, is_local };
+        let upvalues = &mut self.states[state].upvalues;
+        if let Some(existing) = upvalues.iter().position(|u| *u == upvalue) {
+            return existing as u8;
+        }
+
+        if upvalues.len() == MAX_UPVALUES {
+            self.error(span, "Too many closure variables in function.");
+            return 0;
+        }
+        upvalues.push(upvalue);
+        (upvalues.len() - 1) as u8
+    }
+
+    // locals live in stack slots; at the top level the name goes to the globals table instead
+    fn declare_variable(&mut self, name: &Token) -> Option<u16> {
+        if self.state().scope_depth == 0 {
+            return Some(self.identifier_constant(name.symbol(), name.span()));
+        }
+        self.add_local(name.symbol(), name.span());
+        None
+    }
+
+    fn define_variable(&mut self, global: Option<u16>, span: Span) {
+        match global {
+            Some(name) => {
+                self.emit_op(OpCode::DefineGlobal, span);
+                self.chunk().write_u16(name, span);
+            }
+            None => self.mark_initialized(),
+        }
+    }
+
+    fn add_local(&mut self, name: &Symbol, span: Span) {
+        if self.state().locals.len() == MAX_LOCALS {
+            self.error(span, "Too many local variables in function.");
+            return;
+        }
+        self.state_mut().locals.push(Local {
+            name: name.clone(),
+            depth: None,
+            is_captured: false,
+        });
+    }
+
+    fn mark_initialized(&mut self) {
+        let state = self.state_mut();
+        if state.scope_depth == 0 {
+            return;
+        }
+        let depth = state.scope_depth;
+        if let Some(local) = state.locals.last_mut() {
+            local.depth = Some(depth);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.state_mut().scope_depth += 1;
+    }
+
+    // pops the scope's locals, moving any that were captured onto the heap
+    fn end_scope(&mut self, span: Span) {
+        self.state_mut().scope_depth -= 1;
+        let depth = self.state().scope_depth;
+
+        while let Some(local) = self.state().locals.last() {
+            if local.depth.is_some_and(|d| d <= depth) {
+                break;
+            }
+            let op = if local.is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            self.emit_op(op, span);
+            self.state_mut().locals.pop();
+        }
+    }
+
+    fn identifier_constant(&mut self, name: &Symbol, span: Span) -> u16 {
+        let string = self.heap.intern(name.clone());
+        if let Some(index) = self.state().identifiers.get(&string) {
+            return *index;
+        }
+        let index = self.make_constant(Value::Obj(string), span);
+        self.state_mut().identifiers.insert(string, index);
+        index
+    }
+
+    fn make_constant(&mut self, value: Value, span: Span) -> u16 {
+        match self.chunk().add_constant(value) {
+            Some(index) => index,
+            None => {
+                self.error(span, "Too many constants in one chunk.");
+                0
+            }
+        }
+    }
+
+    fn emit_constant(&mut self, value: Value, span: Span) {
+        let index = self.make_constant(value, span);
+        self.emit_op(OpCode::Constant, span);
+        self.chunk().write_u16(index, span);
+    }
+
+    // initializers always hand back the receiver, every other function defaults to nil
+    fn emit_return(&mut self, span: Span) {
+        if self.state().kind == FunctionKind::Initializer {
+            self.emit_with_byte(OpCode::GetLocal, 0, span);
+        } else {
+            self.emit_op(OpCode::Nil, span);
+        }
+        self.emit_op(OpCode::Return, span);
+    }
+
+    // emits a jump with a placeholder offset and returns where to patch it
+    fn emit_jump(&mut self, op: OpCode, span: Span) -> usize {
+        self.emit_op(op, span);
+        self.chunk().write_u16(u16::MAX, span);
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize, span: Span) {
+        let distance = self.chunk().code.len() - offset - 2;
+        let Ok(distance) = u16::try_from(distance) else {
+            self.error(span, "Too much code to jump over.");
+            return;
+        };
+        let [high, low] = distance.to_be_bytes();
+        self.chunk().code[offset] = high;
+        self.chunk().code[offset + 1] = low;
+    }
+
+    fn emit_loop(&mut self, loop_start: usize, span: Span) {
+        self.emit_op(OpCode::Loop, span);
+        let distance = self.chunk().code.len() - loop_start + 2;
+        let Ok(distance) = u16::try_from(distance) else {
+            self.error(span, "Loop body too large.");
+            return;
+        };
+        self.chunk().write_u16(distance, span);
+    }
+
+    fn emit_op(&mut self, op: OpCode, span: Span) {
+        self.chunk().write_op(op, span);
+    }
+
+    fn emit_with_byte(&mut self, op: OpCode, operand: u8, span: Span) {
+        self.chunk().write_op(op, span);
+        self.chunk().write(operand, span);
+    }
+
+    fn state(&self) -> &FunctionState {
+        self.states.last().expect("function state")
+    }
+
+    fn state_mut(&mut self) -> &mut FunctionState {
+        self.states.last_mut().expect("function state")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.state_mut().chunk
+    }
+
+    fn error(&mut self, span: Span, message: &str) {
+        self.diagnostics
+            .error(DiagnosticKind::Compile, span, String::from(message));
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt::Write;
+
+use crate::bytecode::{
+    chunk::{Chunk, OpCode},
+    heap::{FunctionProto, Heap, Object},
+    value::Value,
+};
+
+// Renders a compiled function and every function nested inside it, one
+// instruction per line:
+//
+//   == <script> ==
+//   0000    1 Constant            0 '1'
+//   0003    | Print
+pub fn disassemble(function: &FunctionProto, heap: &Heap) -> String {
+    let mut out = String::new();
+    disassemble_function(function, heap, &mut out);
+    out
+}
+
+fn disassemble_function(function: &FunctionProto, heap: &Heap, out: &mut String) {
+    let name = match &function.name {
+        Some(name) => name.as_str(),
+        None => "<script>",
+    };
+    let _ = writeln!(out, "== {} ==", name);
+
+    let chunk = &function.chunk;
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        offset = disassemble_instruction(chunk, offset, heap, out);
+    }
+
+    // nested functions live in the constant pool, list them after their parent
+    for constant in &chunk.constants {
+        if let Value::Obj(obj) = constant {
+            if let Object::Function(nested) = heap.get(*obj) {
+                out.push('\n');
+                disassemble_function(nested, heap, out);
+            }
+        }
+    }
+}
+
+// writes the instruction at `offset` and returns the offset of the next one
+pub fn disassemble_instruction(
+    chunk: &Chunk,
+    offset: usize,
+    heap: &Heap,
+    out: &mut String,
+) -> usize {
+    let _ = write!(out, "{:04} ", offset);
+    let line = chunk.spans[offset].line;
+    if offset > 0 && chunk.spans[offset - 1].line == line {
+        out.push_str("   | ");
+    } else {
+        let _ = write!(out, "{:4} ", line);
+    }
+
+    let Ok(op) = OpCode::try_from(chunk.code[offset]) else {
+        let _ = writeln!(out, "Unknown opcode {}", chunk.code[offset]);
+        return offset + 1;
+    };
+    let name = format!("{:?}", op);
+
+    match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Class
+        | OpCode::Method => {
+            let index = chunk.read_u16(offset + 1);
+            let value = heap.format_value(chunk.constants[index as usize]);
+            let _ = writeln!(out, "{:<16} {:4} '{}'", name, index, value);
+            offset + 3
+        }
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::Call => {
+            let _ = writeln!(out, "{:<16} {:4}", name, chunk.code[offset + 1]);
+            offset + 2
+        }
+        OpCode::BuildList => {
+            let _ = writeln!(out, "{:<16} {:4}", name, chunk.read_u16(offset + 1));
+            offset + 3
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+            let distance = chunk.read_u16(offset + 1) as usize;
+            let target = if op == OpCode::Loop {
+                offset + 3 - distance
+            } else {
+                offset + 3 + distance
+            };
+            let _ = writeln!(out, "{:<16} {:4} -> {}", name, offset, target);
+            offset + 3
+        }
+        OpCode::Closure => {
+            let index = chunk.read_u16(offset + 1);
+            let constant = chunk.constants[index as usize];
+            let _ = writeln!(
+                out,
+                "{:<16} {:4} {}",
+                name,
+                index,
+                heap.format_value(constant)
+            );
+
+            let upvalue_count = match constant {
+                Value::Obj(obj) => match heap.get(obj) {
+                    Object::Function(function) => function.upvalue_count,
+                    _ => 0,
+                },
+                _ => 0,
+            };
+
+            let mut next = offset + 3;
+            for _ in 0..upvalue_count {
+                let kind = if chunk.code[next] == 1 {
+                    "local"
+                } else {
+                    "upvalue"
+                };
+                let _ = writeln!(
+                    out,
+                    "{:04}    |                  {} {}",
+                    next,
+                    kind,
+                    chunk.code[next + 1]
+                );
+                next += 2;
+            }
+            next
+        }
+        _ => {
+            let _ = writeln!(out, "{}", name);
+            offset + 1
+        }
+    }
+}
@@ -0,0 +1,509 @@
+use std::{collections::HashMap, mem, rc::Rc};
+
+use crate::{
+    bytecode::{chunk::Chunk, value::Value},
+    lox_list::ListMethod,
+    lox_map::{MapKey, MapMethod, OrderedMap},
+    symbol::Symbol,
+    token::Span,
+};
+
+// Handle to an object owned by the `Heap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(usize);
+
+pub struct FunctionProto {
+    pub name: Option<String>, // None for the top-level script
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+    pub source: Rc<str>, // the code compiled, which the chunk's spans point into
+}
+
+pub struct Closure {
+    pub function: Rc<FunctionProto>,
+    pub upvalues: Vec<ObjRef>,
+}
+
+// A captured variable: it points into the stack while the variable is still
+// in scope and holds the value itself once the variable has been closed over.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Class {
+    pub name: String,
+    pub methods: HashMap<ObjRef, ObjRef>, // keyed by interned name string
+}
+
+pub struct Instance {
+    pub class: ObjRef,
+    pub fields: HashMap<ObjRef, Value>, // keyed by interned name string
+}
+
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: ObjRef,
+}
+
+// A method of a built-in type, bound to the object it was looked up on.
+pub struct NativeMethod {
+    pub receiver: ObjRef,
+    pub method: BuiltinMethod,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BuiltinMethod {
+    List(ListMethod),
+    Map(MapMethod),
+}
+
+impl BuiltinMethod {
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinMethod::List(method) => method.name(),
+            BuiltinMethod::Map(method) => method.name(),
+        }
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            BuiltinMethod::List(method) => method.arity(),
+            BuiltinMethod::Map(method) => method.arity(),
+        }
+    }
+}
+
+// String keys are interned string objects.
+pub type Map = OrderedMap<ObjRef, Value>;
+
+// A function implemented in Rust, given the heap to read its arguments and
+// allocate its result with. It returns an error message for the VM to report.
+pub type NativeFn = dyn Fn(&mut Heap, &[Value]) -> Result<Value, String>;
+
+pub struct Native {
+    pub name: String,
+    pub arity: usize,
+    pub function: Rc<NativeFn>,
+}
+
+pub enum Object {
+    String(Symbol),
+    Function(Rc<FunctionProto>),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+    List(Vec<Value>),
+    Map(Map),
+    NativeMethod(NativeMethod),
+    Native(Native),
+}
+
+impl Object {
+    // rough number of bytes the object keeps alive, used to pace collections
+    fn size(&self) -> usize {
+        let contents = match self {
+            Object::String(s) => s.len(),
+            Object::Function(function) => {
+                let chunk = &function.chunk;
+                chunk.code.len()
+                    + chunk.spans.len() * mem::size_of::<Span>()
+                    + chunk.constants.len() * mem::size_of::<Value>()
+            }
+            Object::Closure(closure) => closure.upvalues.len() * mem::size_of::<ObjRef>(),
+            Object::Class(class) => class.methods.len() * mem::size_of::<(String, ObjRef)>(),
+            Object::Instance(instance) => instance.fields.len() * mem::size_of::<(String, Value)>(),
+            Object::List(elements) => elements.capacity() * mem::size_of::<Value>(),
+            Object::Map(map) => map.len() * mem::size_of::<(MapKey<ObjRef>, Value, usize)>(),
+            Object::Native(native) => native.name.len(),
+            Object::Upvalue(_) | Object::BoundMethod(_) | Object::NativeMethod(_) => 0,
+        };
+        mem::size_of::<Object>() + contents
+    }
+}
+
+// Controls how often the garbage collector runs.
+#[derive(Debug, Clone, Copy)]
+pub struct GcConfig {
+    pub initial_threshold: usize, // bytes allocated before the first collection
+    pub growth_factor: usize,     // after a collection the threshold becomes live bytes times this
+    pub stress: bool,             // collect before every allocation, to flush out rooting bugs
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            initial_threshold: 1024 * 1024,
+            growth_factor: 2,
+            stress: false,
+        }
+    }
+}
+
+// Owns every object the VM allocates. Memory is reclaimed by a mark-and-sweep
+// collector: the owner marks its roots, `collect` traces everything reachable
+// from them and frees the rest. Freed slots are reused by later allocations.
+// Strings are interned, so two string objects are equal only if they are the
+// same object.
+pub struct Heap {
+    objects: Vec<Option<Object>>,
+    marks: Vec<bool>,
+    strings: HashMap<Symbol, ObjRef>, // the one object for each string's text
+    free: Vec<usize>,                 // indexes of slots freed by the last sweeps
+    gray: Vec<ObjRef>,                // marked objects whose references haven't been traced yet
+    bytes_allocated: usize,
+    next_gc: usize,
+    config: GcConfig,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self::with_config(GcConfig::default())
+    }
+
+    pub fn with_config(config: GcConfig) -> Self {
+        Self {
+            objects: vec![],
+            marks: vec![],
+            strings: HashMap::new(),
+            free: vec![],
+            gray: vec![],
+            bytes_allocated: 0,
+            next_gc: config.initial_threshold,
+            config,
+        }
+    }
+
+    // never collects; callers check `should_collect` first while their roots are reachable.
+    // Strings must be allocated through `intern` instead.
+    pub fn alloc(&mut self, object: Object) -> ObjRef {
+        self.bytes_allocated += object.size();
+        let string = match &object {
+            Object::String(symbol) => Some(symbol.clone()),
+            _ => None,
+        };
+
+        let obj = match self.free.pop() {
+            Some(index) => {
+                self.objects[index] = Some(object);
+                ObjRef(index)
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.marks.push(false);
+                ObjRef(self.objects.len() - 1)
+            }
+        };
+
+        if let Some(symbol) = string {
+            self.strings.insert(symbol, obj);
+        }
+        obj
+    }
+
+    // the string object for `symbol`, allocating it the first time it is seen
+    pub fn intern(&mut self, symbol: Symbol) -> ObjRef {
+        match self.find_string(&symbol) {
+            Some(existing) => existing,
+            None => self.alloc(Object::String(symbol)),
+        }
+    }
+
+    pub fn find_string(&self, symbol: &Symbol) -> Option<ObjRef> {
+        self.strings.get(symbol).copied()
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.config.stress || self.bytes_allocated > self.next_gc
+    }
+
+    pub fn live_objects(&self) -> usize {
+        self.objects.len() - self.free.len()
+    }
+
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    pub fn mark_value(&mut self, value: Value) {
+        if let Value::Obj(obj) = value {
+            self.mark_object(obj);
+        }
+    }
+
+    pub fn mark_object(&mut self, obj: ObjRef) {
+        if !self.marks[obj.0] {
+            self.marks[obj.0] = true;
+            self.gray.push(obj);
+        }
+    }
+
+    // traces from the marked roots, then frees every object that wasn't reached
+    pub fn collect(&mut self) {
+        while let Some(obj) = self.gray.pop() {
+            self.blacken(obj);
+        }
+        self.sweep();
+        self.next_gc =
+            (self.bytes_allocated * self.config.growth_factor).max(self.config.initial_threshold);
+    }
+
+    fn blacken(&mut self, obj: ObjRef) {
+        let mut references = vec![];
+        match self.get(obj) {
+            Object::String(_) => {}
+            Object::Function(function) => references.extend(&function.chunk.constants),
+            Object::Closure(closure) => {
+                references.extend(&closure.function.chunk.constants);
+                references.extend(closure.upvalues.iter().map(|u| Value::Obj(*u)));
+            }
+            Object::Upvalue(Upvalue::Closed(value)) => references.push(*value),
+            Object::Upvalue(Upvalue::Open(_)) => {}
+            Object::Class(class) => {
+                for (name, method) in &class.methods {
+                    references.push(Value::Obj(*name));
+                    references.push(Value::Obj(*method));
+                }
+            }
+            Object::Instance(instance) => {
+                references.push(Value::Obj(instance.class));
+                for (name, value) in &instance.fields {
+                    references.push(Value::Obj(*name));
+                    references.push(*value);
+                }
+            }
+            Object::BoundMethod(bound) => {
+                references.push(bound.receiver);
+                references.push(Value::Obj(bound.method));
+            }
+            Object::List(elements) => references.extend(elements),
+            Object::Map(map) => {
+                for (key, value) in map.iter() {
+                    references.push(key_value(key));
+                    references.push(*value);
+                }
+            }
+            Object::NativeMethod(native) => references.push(Value::Obj(native.receiver)),
+            Object::Native(_) => {}
+        }
+
+        for value in references {
+            self.mark_value(value);
+        }
+    }
+
+    fn sweep(&mut self) {
+        self.bytes_allocated = 0;
+        for (index, slot) in self.objects.iter_mut().enumerate() {
+            let Some(object) = slot else {
+                continue;
+            };
+
+            if self.marks[index] {
+                self.marks[index] = false;
+                self.bytes_allocated += object.size();
+            } else {
+                if let Object::String(symbol) = object {
+                    self.strings.remove(symbol);
+                }
+                *slot = None;
+                self.free.push(index);
+            }
+        }
+    }
+
+    pub fn get(&self, obj: ObjRef) -> &Object {
+        self.objects[obj.0]
+            .as_ref()
+            .expect("use of a collected object")
+    }
+
+    pub fn get_mut(&mut self, obj: ObjRef) -> &mut Object {
+        self.objects[obj.0]
+            .as_mut()
+            .expect("use of a collected object")
+    }
+
+    pub fn string(&self, obj: ObjRef) -> &Symbol {
+        match self.get(obj) {
+            Object::String(s) => s,
+            _ => panic!("expected a string object"),
+        }
+    }
+
+    pub fn closure(&self, obj: ObjRef) -> &Closure {
+        match self.get(obj) {
+            Object::Closure(closure) => closure,
+            _ => panic!("expected a closure object"),
+        }
+    }
+
+    pub fn class(&self, obj: ObjRef) -> &Class {
+        match self.get(obj) {
+            Object::Class(class) => class,
+            _ => panic!("expected a class object"),
+        }
+    }
+
+    pub fn class_mut(&mut self, obj: ObjRef) -> &mut Class {
+        match self.get_mut(obj) {
+            Object::Class(class) => class,
+            _ => panic!("expected a class object"),
+        }
+    }
+
+    pub fn instance(&self, obj: ObjRef) -> &Instance {
+        match self.get(obj) {
+            Object::Instance(instance) => instance,
+            _ => panic!("expected an instance object"),
+        }
+    }
+
+    pub fn instance_mut(&mut self, obj: ObjRef) -> &mut Instance {
+        match self.get_mut(obj) {
+            Object::Instance(instance) => instance,
+            _ => panic!("expected an instance object"),
+        }
+    }
+
+    pub fn list(&self, obj: ObjRef) -> &Vec<Value> {
+        match self.get(obj) {
+            Object::List(elements) => elements,
+            _ => panic!("expected a list object"),
+        }
+    }
+
+    pub fn list_mut(&mut self, obj: ObjRef) -> &mut Vec<Value> {
+        match self.get_mut(obj) {
+            Object::List(elements) => elements,
+            _ => panic!("expected a list object"),
+        }
+    }
+
+    pub fn map(&self, obj: ObjRef) -> &Map {
+        match self.get(obj) {
+            Object::Map(map) => map,
+            _ => panic!("expected a map object"),
+        }
+    }
+
+    pub fn map_mut(&mut self, obj: ObjRef) -> &mut Map {
+        match self.get_mut(obj) {
+            Object::Map(map) => map,
+            _ => panic!("expected a map object"),
+        }
+    }
+
+    pub fn upvalue(&self, obj: ObjRef) -> &Upvalue {
+        match self.get(obj) {
+            Object::Upvalue(upvalue) => upvalue,
+            _ => panic!("expected an upvalue object"),
+        }
+    }
+
+    pub fn upvalue_mut(&mut self, obj: ObjRef) -> &mut Upvalue {
+        match self.get_mut(obj) {
+            Object::Upvalue(upvalue) => upvalue,
+            _ => panic!("expected an upvalue object"),
+        }
+    }
+
+    // objects only equal themselves; interning makes that true of strings with the same text
+    pub fn values_equal(&self, a: Value, b: Value) -> bool {
+        match (a, b) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Obj(a), Value::Obj(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    // formats a value the same way the tree-walking interpreter prints a LoxValue
+    pub fn format_value(&self, value: Value) -> String {
+        self.format_nested(value, &mut vec![])
+    }
+
+    // `enclosing` holds the lists and maps being formatted around this value,
+    // so one inside itself shows as `[...]` or `{...}` where it recurs
+    fn format_nested(&self, value: Value, enclosing: &mut Vec<ObjRef>) -> String {
+        match value {
+            Value::Nil => String::from("nil"),
+            Value::Bool(b) => b.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::Obj(obj) => self.format_object(obj, enclosing),
+        }
+    }
+
+    // inside a list or map, strings are quoted
+    fn format_element(&self, value: Value, enclosing: &mut Vec<ObjRef>) -> String {
+        match value {
+            Value::Obj(s) if matches!(self.get(s), Object::String(_)) => {
+                format!("\"{}\"", self.string(s))
+            }
+            value => self.format_nested(value, enclosing),
+        }
+    }
+
+    fn format_object(&self, obj: ObjRef, enclosing: &mut Vec<ObjRef>) -> String {
+        match self.get(obj) {
+            Object::List(_) if enclosing.contains(&obj) => String::from("[...]"),
+            Object::List(elements) => {
+                enclosing.push(obj);
+                let elements: Vec<String> = elements
+                    .iter()
+                    .map(|element| self.format_element(*element, enclosing))
+                    .collect();
+                enclosing.pop();
+                format!("[{}]", elements.join(", "))
+            }
+            Object::Map(_) if enclosing.contains(&obj) => String::from("{...}"),
+            Object::Map(map) => {
+                enclosing.push(obj);
+                let entries: Vec<String> = map
+                    .iter()
+                    .map(|(key, value)| {
+                        let key = self.format_element(key_value(key), enclosing);
+                        format!("{}: {}", key, self.format_element(*value, enclosing))
+                    })
+                    .collect();
+                enclosing.pop();
+                format!("{{{}}}", entries.join(", "))
+            }
+            Object::NativeMethod(native) => format!("<native fn {}>", native.method.name()),
+            Object::Native(native) => format!("<native fn {}>", native.name),
+            Object::String(s) => s.to_string(),
+            Object::Function(function) => format_function(function),
+            Object::Closure(closure) => format_function(&closure.function),
+            Object::Upvalue(_) => String::from("upvalue"),
+            Object::Class(class) => class.name.clone(),
+            Object::Instance(instance) => format!("{} instance", self.class(instance.class).name),
+            Object::BoundMethod(bound) => format_function(&self.closure(bound.method).function),
+        }
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn key_value(key: &MapKey<ObjRef>) -> Value {
+    match key {
+        MapKey::Nil => Value::Nil,
+        MapKey::Bool(b) => Value::Bool(*b),
+        MapKey::Number(bits) => Value::Number(f64::from_bits(*bits)),
+        MapKey::Str(s) => Value::Obj(*s),
+    }
+}
+
+fn format_function(function: &FunctionProto) -> String {
+    match &function.name {
+        Some(name) => format!("<fn {}>", name),
+        None => String::from("<script>"),
+    }
+}
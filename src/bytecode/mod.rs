@@ -0,0 +1,11 @@
+// A second backend: the resolved syntax tree is compiled to bytecode and run
+// on a stack-based virtual machine instead of being walked directly.
+pub mod chunk;
+pub mod compiler;
+pub mod disassembler;
+pub mod heap;
+pub mod prelude;
+pub mod value;
+pub mod vm;
+
+pub use vm::Vm;
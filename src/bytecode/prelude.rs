@@ -0,0 +1,65 @@
+use crate::{
+    bytecode::{
+        heap::{Heap, Object},
+        value::Value,
+        Vm,
+    },
+    prelude::{self, clock, no_length, not_a_number},
+    symbol::Symbol,
+};
+
+// The same functions `prelude::define` gives the tree-walking interpreter.
+pub fn define(vm: &mut Vm) {
+    vm.define_native("clock", 0, |_, _| Ok(Value::Number(clock())));
+    vm.define_native("type", 1, |heap, arguments| {
+        let name = type_name(heap, arguments[0]);
+        Ok(Value::Obj(heap.intern(Symbol::intern(name))))
+    });
+    vm.define_native("str", 1, |heap, arguments| {
+        let text = heap.format_value(arguments[0]);
+        Ok(Value::Obj(heap.intern(Symbol::intern(&text))))
+    });
+    vm.define_native("num", 1, |heap, arguments| match arguments[0] {
+        Value::Number(n) => Ok(Value::Number(n)),
+        Value::Obj(s) if matches!(heap.get(s), Object::String(_)) => {
+            prelude::parse_number(heap.string(s)).map(Value::Number)
+        }
+        value => Err(not_a_number(&heap.format_value(value))),
+    });
+    vm.define_native("len", 1, |heap, arguments| {
+        let len = match arguments[0] {
+            Value::Obj(obj) => match heap.get(obj) {
+                Object::String(s) => Some(s.chars().count()),
+                Object::List(elements) => Some(elements.len()),
+                Object::Map(map) => Some(map.len()),
+                _ => None,
+            },
+            _ => None,
+        };
+        match len {
+            Some(len) => Ok(Value::Number(len as f64)),
+            None => Err(no_length(&heap.format_value(arguments[0]))),
+        }
+    });
+}
+
+fn type_name(heap: &Heap, value: Value) -> &'static str {
+    match value {
+        Value::Nil => "nil",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::Obj(obj) => match heap.get(obj) {
+            Object::String(_) => "string",
+            Object::Function(_)
+            | Object::Closure(_)
+            | Object::BoundMethod(_)
+            | Object::NativeMethod(_)
+            | Object::Native(_) => "function",
+            Object::Class(_) => "class",
+            Object::Instance(_) => "instance",
+            Object::List(_) => "list",
+            Object::Map(_) => "map",
+            Object::Upvalue(_) => unreachable!("upvalues are never values"),
+        },
+    }
+}
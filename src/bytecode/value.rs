@@ -0,0 +1,18 @@
+use crate::bytecode::heap::ObjRef;
+
+// A value on the VM stack. Anything larger than a number lives on the heap
+// and is referred to by handle.
+#[derive(Debug, Clone, Copy)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Obj(ObjRef),
+}
+
+impl Value {
+    // false and nil are falsey, everything else is truthy
+    pub fn is_falsey(&self) -> bool {
+        matches!(self, Value::Nil | Value::Bool(false))
+    }
+}
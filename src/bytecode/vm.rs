@@ -0,0 +1,1063 @@
+use std::{collections::HashMap, fs, path::Path, rc::Rc};
+
+use crate::{
+    bytecode::{
+        chunk::OpCode,
+        compiler, disassembler,
+        heap::{
+            self, BoundMethod, BuiltinMethod, Class, Closure, FunctionProto, GcConfig, Heap,
+            Instance, Map, Native, NativeMethod, ObjRef, Object, Upvalue,
+        },
+        prelude,
+        value::Value,
+    },
+    debugger::OutputSink,
+    errors::{Diagnostics, LoxError, RuntimeError, RuntimeErrorType},
+    frontend::{self, Parse},
+    linter::LintConfig,
+    lox_list::{self, ListMethod},
+    lox_map::{MapKey, MapMethod},
+    symbol::Symbol,
+    token::Span,
+};
+
+pub(crate) const FRAMES_MAX: usize = 1024;
+
+struct CallFrame {
+    closure: ObjRef,
+    function: Rc<FunctionProto>,
+    ip: usize,    // offset of the next byte to execute
+    slots: usize, // stack index of the frame's slot zero
+}
+
+// Runs programs by compiling them to bytecode first. Behaves like
+// `Interpreter`, including keeping globals alive between calls to `eval` and
+// starting out with the prelude defined.
+pub struct Vm {
+    heap: Heap,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<ObjRef, Value>, // keyed by interned name string
+    open_upvalues: Vec<ObjRef>,      // upvalues still pointing into the stack, sorted by slot
+    init_string: ObjRef,             // name of class initializers, looked up on every instantiation
+    lint: LintConfig,
+    warnings: Diagnostics,      // found in the source last run
+    output: Option<OutputSink>, // where `print` writes, if not stdout
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::with_gc_config(GcConfig::default())
+    }
+
+    pub fn with_gc_config(config: GcConfig) -> Self {
+        let mut heap = Heap::with_config(config);
+        let init_string = heap.intern(Symbol::intern("init"));
+
+        let mut vm = Self {
+            heap,
+            stack: vec![],
+            frames: vec![],
+            globals: HashMap::new(),
+            open_upvalues: vec![],
+            init_string,
+            lint: LintConfig::default(),
+            warnings: Diagnostics::new(),
+            output: None,
+        };
+        prelude::define(&mut vm);
+        vm
+    }
+
+    // like `Interpreter::define_native`, for functions working on the VM's values
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&mut Heap, &[Value]) -> Result<Value, String> + 'static,
+    ) {
+        let name_string = self.heap.intern(Symbol::intern(name));
+        let native = self.heap.alloc(Object::Native(Native {
+            name: String::from(name),
+            arity,
+            function: Rc::new(function),
+        }));
+        self.globals.insert(name_string, Value::Obj(native));
+    }
+
+    pub fn eval(&mut self, source: &str) -> Result<(), LoxError> {
+        self.execute(source, frontend::parse).map(|_| ())
+    }
+
+    // evaluates `source` as a single expression and returns its value,
+    // formatted the way `print` shows it
+    pub fn eval_expression(&mut self, source: &str) -> Result<String, LoxError> {
+        let value = self.execute(source, frontend::parse_expression)?;
+        Ok(self.heap.format_value(value))
+    }
+
+    fn execute(&mut self, source: &str, parse: Parse) -> Result<Value, LoxError> {
+        let mut diagnostics = Diagnostics::with_source(source);
+        let Some(function) = self.compile(source, parse, &mut diagnostics) else {
+            self.warnings = Diagnostics::new();
+            return Err(LoxError::Compile(diagnostics));
+        };
+        self.warnings = diagnostics.clone();
+
+        let closure = self.heap.alloc(Object::Closure(Closure {
+            function: Rc::clone(&function),
+            upvalues: vec![],
+        }));
+        self.stack.push(Value::Obj(closure));
+        self.frames.push(CallFrame {
+            closure,
+            function,
+            ip: 0,
+            slots: 0,
+        });
+
+        self.run().map_err(|e| {
+            diagnostics.runtime_error(&e);
+            self.reset();
+            LoxError::Runtime(diagnostics)
+        })
+    }
+
+    // compiles `source` without running it and lists the resulting bytecode
+    pub fn disassemble(&mut self, source: &str, parse: Parse) -> Result<String, LoxError> {
+        let mut diagnostics = Diagnostics::with_source(source);
+        match self.compile(source, parse, &mut diagnostics) {
+            Some(function) => Ok(disassembler::disassemble(&function, &self.heap)),
+            None => Err(LoxError::Compile(diagnostics)),
+        }
+    }
+
+    pub fn run_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), LoxError> {
+        let source = fs::read_to_string(path)?;
+        self.eval(&source)
+    }
+
+    // like `Interpreter::redirect_output`
+    pub fn redirect_output(&mut self, output: OutputSink) {
+        self.output = Some(output);
+    }
+
+    // like `Interpreter::set_lint_config`
+    pub fn set_lint_config(&mut self, config: LintConfig) {
+        self.lint = config;
+    }
+
+    // like `Interpreter::warnings`
+    pub fn warnings(&self) -> &Diagnostics {
+        &self.warnings
+    }
+
+    // every global defined so far, sorted by name, with values formatted the
+    // way `print` shows them
+    pub fn globals(&self) -> Vec<(Symbol, String)> {
+        let mut globals: Vec<(Symbol, String)> = self
+            .globals
+            .iter()
+            .map(|(name, value)| {
+                let name = self.heap.string(*name).clone();
+                (name, self.heap.format_value(*value))
+            })
+            .collect();
+        globals.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        globals
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    // frees every object that can no longer be reached from the stack, the call
+    // frames, the globals or a captured variable
+    pub fn collect_garbage(&mut self) {
+        for value in &self.stack {
+            self.heap.mark_value(*value);
+        }
+        for frame in &self.frames {
+            self.heap.mark_object(frame.closure);
+        }
+        for (name, value) in &self.globals {
+            self.heap.mark_object(*name);
+            self.heap.mark_value(*value);
+        }
+        self.heap.mark_object(self.init_string);
+        for upvalue in &self.open_upvalues {
+            self.heap.mark_object(*upvalue);
+        }
+        self.heap.collect();
+    }
+
+    fn intern(&mut self, symbol: Symbol) -> ObjRef {
+        match self.heap.find_string(&symbol) {
+            Some(existing) => existing,
+            None => self.alloc(Object::String(symbol)),
+        }
+    }
+
+    // every allocation made while running goes through here so the heap is
+    // collected on schedule; anything the caller still needs must be reachable
+    fn alloc(&mut self, object: Object) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        self.heap.alloc(object)
+    }
+
+    fn compile(
+        &mut self,
+        source: &str,
+        parse: Parse,
+        diagnostics: &mut Diagnostics,
+    ) -> Option<Rc<FunctionProto>> {
+        let statements = parse(source, self.lint, diagnostics);
+        if diagnostics.has_errors() {
+            return None;
+        }
+
+        let source = Rc::from(source);
+        let function = compiler::compile(&statements, &source, &mut self.heap, diagnostics);
+        if diagnostics.has_errors() {
+            return None;
+        }
+        Some(function)
+    }
+
+    fn reset(&mut self) {
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+    }
+
+    fn run(&mut self) -> Result<Value, RuntimeError> {
+        loop {
+            let op_start = self.frame().ip;
+            let byte = self.read_byte();
+            let op = OpCode::try_from(byte).expect("invalid opcode");
+
+            match op {
+                OpCode::Constant => {
+                    let value = self.read_constant();
+                    self.push(value);
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Bool(true)),
+                OpCode::False => self.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.push(self.stack[slot]);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().slots + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_name();
+                    match self.globals.get(&name) {
+                        Some(value) => self.push(*value),
+                        None => {
+                            return Err(self.error(
+                                op_start,
+                                self.heap.string(name).to_string(),
+                                RuntimeErrorType::UndefinedVariable,
+                            ))
+                        }
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_name();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_name();
+                    let value = self.peek(0);
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => {
+                            return Err(self.error(
+                                op_start,
+                                self.heap.string(name).to_string(),
+                                RuntimeErrorType::UndefinedVariable,
+                            ))
+                        }
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let upvalue = self.read_upvalue();
+                    let value = match self.heap.upvalue(upvalue) {
+                        Upvalue::Open(slot) => self.stack[*slot],
+                        Upvalue::Closed(value) => *value,
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let upvalue = self.read_upvalue();
+                    let value = self.peek(0);
+                    match self.heap.upvalue_mut(upvalue) {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_name();
+                    let receiver = self.peek(0);
+                    if let Some(method) = self.builtin_method(receiver, name, op_start)? {
+                        self.pop();
+                        self.push(method);
+                        continue;
+                    }
+                    let Some(instance) = self.as_instance(receiver) else {
+                        return Err(self.error(
+                            op_start,
+                            self.heap.format_value(receiver),
+                            RuntimeErrorType::OnlyInstancesHaveProperties,
+                        ));
+                    };
+
+                    let instance = self.heap.instance(instance);
+                    let value = match instance.fields.get(&name) {
+                        Some(value) => *value,
+                        None => self.bind_method(instance.class, name, receiver, op_start)?,
+                    };
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_name();
+                    let receiver = self.peek(1);
+                    let Some(instance) = self.as_instance(receiver) else {
+                        return Err(self.error(
+                            op_start,
+                            self.heap.format_value(receiver),
+                            RuntimeErrorType::OnlyInstancesHaveProperties,
+                        ));
+                    };
+
+                    let value = self.pop();
+                    self.heap.instance_mut(instance).fields.insert(name, value);
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_name();
+                    // both stay on the stack until the method is bound so a collection can't free them
+                    let (receiver, superclass) = (self.peek(1), self.peek(0));
+                    let Value::Obj(superclass) = superclass else {
+                        unreachable!("super is always bound to a class");
+                    };
+                    let method = self.bind_method(superclass, name, receiver, op_start)?;
+                    self.pop_pair();
+                    self.push(method);
+                }
+                OpCode::Equal => {
+                    let (a, b) = self.pop_pair();
+                    self.push(Value::Bool(self.heap.values_equal(a, b)));
+                }
+                OpCode::NotEqual => {
+                    let (a, b) = self.pop_pair();
+                    self.push(Value::Bool(!self.heap.values_equal(a, b)));
+                }
+                OpCode::Greater => {
+                    let (a, b) = self.pop_numbers(op, op_start)?;
+                    self.push(Value::Bool(a > b));
+                }
+                OpCode::GreaterEqual => {
+                    let (a, b) = self.pop_numbers(op, op_start)?;
+                    self.push(Value::Bool(a >= b));
+                }
+                OpCode::Less => {
+                    let (a, b) = self.pop_numbers(op, op_start)?;
+                    self.push(Value::Bool(a < b));
+                }
+                OpCode::LessEqual => {
+                    let (a, b) = self.pop_numbers(op, op_start)?;
+                    self.push(Value::Bool(a <= b));
+                }
+                OpCode::Add => self.add(op_start)?,
+                OpCode::Subtract => {
+                    let (a, b) = self.pop_numbers(op, op_start)?;
+                    self.push(Value::Number(a - b));
+                }
+                OpCode::Multiply => {
+                    let (a, b) = self.pop_numbers(op, op_start)?;
+                    self.push(Value::Number(a * b));
+                }
+                OpCode::Divide => {
+                    let (a, b) = self.pop_numbers(op, op_start)?;
+                    if b == 0.0 {
+                        return Err(self.error(
+                            op_start,
+                            String::from("/"),
+                            RuntimeErrorType::DivideByZero,
+                        ));
+                    }
+                    self.push(Value::Number(a / b));
+                }
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::Bool(value.is_falsey()));
+                }
+                OpCode::Negate => match self.pop() {
+                    Value::Number(n) => self.push(Value::Number(-n)),
+                    _ => {
+                        return Err(self.error(
+                            op_start,
+                            String::from("-"),
+                            RuntimeErrorType::OperandMustBeNumber,
+                        ))
+                    }
+                },
+                OpCode::Print => {
+                    let value = self.pop();
+                    let text = self.heap.format_value(value);
+                    match &mut self.output {
+                        Some(output) => output(&text),
+                        None => println!("{}", text),
+                    }
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16();
+                    self.frame_mut().ip += offset as usize;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16();
+                    if self.peek(0).is_falsey() {
+                        self.frame_mut().ip += offset as usize;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16();
+                    self.frame_mut().ip -= offset as usize;
+                }
+                OpCode::Call => {
+                    let argument_count = self.read_byte() as usize;
+                    let callee = self.peek(argument_count);
+                    self.call_value(callee, argument_count, op_start)?;
+                }
+                OpCode::Closure => {
+                    let Value::Obj(function) = self.read_constant() else {
+                        unreachable!("closure operand is always a function");
+                    };
+                    let Object::Function(function) = self.heap.get(function) else {
+                        unreachable!("closure operand is always a function");
+                    };
+                    let function = Rc::clone(function);
+
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        let upvalue = if is_local {
+                            self.capture_upvalue(self.frame().slots + index)
+                        } else {
+                            self.heap.closure(self.frame().closure).upvalues[index]
+                        };
+                        upvalues.push(upvalue);
+                    }
+
+                    let closure = self.alloc(Object::Closure(Closure { function, upvalues }));
+                    self.push(Value::Obj(closure));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("call frame");
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+                    self.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_name();
+                    let class = self.alloc(Object::Class(Class {
+                        name: self.heap.string(name).to_string(),
+                        methods: HashMap::new(),
+                    }));
+                    self.push(Value::Obj(class));
+                }
+                OpCode::Inherit => {
+                    let superclass = self.peek(1);
+                    let Some(superclass) = self.as_class(superclass) else {
+                        return Err(self.error(
+                            op_start,
+                            self.heap.format_value(superclass),
+                            RuntimeErrorType::SuperclassMustBeClass,
+                        ));
+                    };
+
+                    // methods are copied down so lookups never have to walk the superclass chain
+                    let methods = self.heap.class(superclass).methods.clone();
+                    let Value::Obj(subclass) = self.pop() else {
+                        unreachable!("inherit operand is always a class");
+                    };
+                    self.heap.class_mut(subclass).methods.extend(methods);
+                }
+                OpCode::Method => {
+                    let name = self.read_name();
+                    let (Value::Obj(class), Value::Obj(method)) = (self.peek(1), self.peek(0))
+                    else {
+                        unreachable!("methods are always closures defined on a class");
+                    };
+                    self.heap.class_mut(class).methods.insert(name, method);
+                    self.pop();
+                }
+                OpCode::BuildList => {
+                    let length = self.read_u16() as usize;
+                    let start = self.stack.len() - length;
+                    // the elements stay on the stack until the list holding them is allocated
+                    let elements = self.stack[start..].to_vec();
+                    let list = self.alloc(Object::List(elements));
+                    self.stack.truncate(start);
+                    self.push(Value::Obj(list));
+                }
+                OpCode::GetIndex => {
+                    let (object, index) = (self.peek(1), self.peek(0));
+                    let value = if let Some(list) = self.as_list(object) {
+                        let position = self.list_position(list, index, false, op_start)?;
+                        self.heap.list(list)[position]
+                    } else if let Some(map) = self.as_map(object) {
+                        let key = self.map_key(index, op_start)?;
+                        match self.heap.map(map).get(&key) {
+                            Some(value) => *value,
+                            None => {
+                                return Err(self.error(
+                                    op_start,
+                                    self.heap.format_value(index),
+                                    RuntimeErrorType::UndefinedKey,
+                                ))
+                            }
+                        }
+                    } else {
+                        return Err(self.not_indexable(object, op_start));
+                    };
+                    self.pop_pair();
+                    self.push(value);
+                }
+                OpCode::SetIndex => {
+                    let (object, index, value) = (self.peek(2), self.peek(1), self.peek(0));
+                    if let Some(list) = self.as_list(object) {
+                        let position = self.list_position(list, index, false, op_start)?;
+                        self.heap.list_mut(list)[position] = value;
+                    } else if let Some(map) = self.as_map(object) {
+                        let key = self.map_key(index, op_start)?;
+                        self.heap.map_mut(map).insert(key, value);
+                    } else {
+                        return Err(self.not_indexable(object, op_start));
+                    }
+                    self.stack.truncate(self.stack.len() - 3);
+                    self.push(value);
+                }
+                OpCode::NewMap => {
+                    let map = self.alloc(Object::Map(Map::new()));
+                    self.push(Value::Obj(map));
+                }
+                OpCode::MapEntry => {
+                    let (Value::Obj(map), key, value) = (self.peek(2), self.peek(1), self.peek(0))
+                    else {
+                        unreachable!("map entries are always added to the map below them");
+                    };
+                    let key = self.map_key(key, op_start)?;
+                    self.heap.map_mut(map).insert(key, value);
+                    self.pop_pair();
+                }
+            }
+        }
+    }
+
+    fn call_value(
+        &mut self,
+        callee: Value,
+        argument_count: usize,
+        op_start: usize,
+    ) -> Result<(), RuntimeError> {
+        let slot = self.stack.len() - argument_count - 1;
+
+        if let Value::Obj(obj) = callee {
+            match self.heap.get(obj) {
+                Object::Closure(closure) => {
+                    let function = Rc::clone(&closure.function);
+                    return self.call(obj, function, argument_count, op_start);
+                }
+                Object::Class(class) => {
+                    let initializer = class.methods.get(&self.init_string).copied();
+                    let instance = self.alloc(Object::Instance(Instance {
+                        class: obj,
+                        fields: HashMap::new(),
+                    }));
+                    self.stack[slot] = Value::Obj(instance);
+
+                    return match initializer {
+                        Some(initializer) => {
+                            let function = Rc::clone(&self.heap.closure(initializer).function);
+                            self.call(initializer, function, argument_count, op_start)
+                        }
+                        None if argument_count != 0 => Err(self.error(
+                            op_start,
+                            format!("expected 0 but got {}", argument_count),
+                            RuntimeErrorType::ArityMismatch,
+                        )),
+                        None => Ok(()),
+                    };
+                }
+                Object::BoundMethod(bound) => {
+                    let method = bound.method;
+                    self.stack[slot] = bound.receiver;
+                    let function = Rc::clone(&self.heap.closure(method).function);
+                    return self.call(method, function, argument_count, op_start);
+                }
+                Object::Native(native) => {
+                    if argument_count != native.arity {
+                        return Err(self.error(
+                            op_start,
+                            format!("expected {} but got {}", native.arity, argument_count),
+                            RuntimeErrorType::ArityMismatch,
+                        ));
+                    }
+                    // the arguments stay on the stack while the function runs
+                    let function = Rc::clone(&native.function);
+                    let arguments = self.stack[slot + 1..].to_vec();
+                    let result = function(&mut self.heap, &arguments).map_err(|cause| {
+                        self.error(op_start, cause, RuntimeErrorType::NativeError)
+                    })?;
+                    self.stack.truncate(slot);
+                    self.push(result);
+                    return Ok(());
+                }
+                Object::NativeMethod(native) => {
+                    let (receiver, method) = (native.receiver, native.method);
+                    if argument_count != method.arity() {
+                        return Err(self.error(
+                            op_start,
+                            format!("expected {} but got {}", method.arity(), argument_count),
+                            RuntimeErrorType::ArityMismatch,
+                        ));
+                    }
+                    let result = match method {
+                        BuiltinMethod::List(method) => {
+                            self.call_list_method(receiver, method, op_start)?
+                        }
+                        BuiltinMethod::Map(method) => {
+                            self.call_map_method(receiver, method, op_start)?
+                        }
+                    };
+                    self.stack.truncate(slot);
+                    self.push(result);
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        Err(self.error(
+            op_start,
+            self.heap.format_value(callee),
+            RuntimeErrorType::NotCallable,
+        ))
+    }
+
+    fn call(
+        &mut self,
+        closure: ObjRef,
+        function: Rc<FunctionProto>,
+        argument_count: usize,
+        op_start: usize,
+    ) -> Result<(), RuntimeError> {
+        if argument_count != function.arity {
+            return Err(self.error(
+                op_start,
+                format!("expected {} but got {}", function.arity, argument_count),
+                RuntimeErrorType::ArityMismatch,
+            ));
+        }
+
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.error(
+                op_start,
+                self.heap.format_value(Value::Obj(closure)),
+                RuntimeErrorType::StackOverflow,
+            ));
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            function,
+            ip: 0,
+            slots: self.stack.len() - argument_count - 1,
+        });
+        Ok(())
+    }
+
+    fn bind_method(
+        &mut self,
+        class: ObjRef,
+        name: ObjRef,
+        receiver: Value,
+        op_start: usize,
+    ) -> Result<Value, RuntimeError> {
+        let Some(method) = self.heap.class(class).methods.get(&name).copied() else {
+            return Err(self.error(
+                op_start,
+                self.heap.string(name).to_string(),
+                RuntimeErrorType::UndefinedProperty,
+            ));
+        };
+
+        let bound = self.alloc(Object::BoundMethod(BoundMethod { receiver, method }));
+        Ok(Value::Obj(bound))
+    }
+
+    // the method `name` of a list or map, or None if the receiver is neither
+    fn builtin_method(
+        &mut self,
+        receiver: Value,
+        name: ObjRef,
+        op_start: usize,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let Value::Obj(obj) = receiver else {
+            return Ok(None);
+        };
+        let name_text = self.heap.string(name).as_str();
+        let method = match self.heap.get(obj) {
+            Object::List(_) => ListMethod::from_name(name_text).map(BuiltinMethod::List),
+            Object::Map(_) => MapMethod::from_name(name_text).map(BuiltinMethod::Map),
+            _ => return Ok(None),
+        };
+        let Some(method) = method else {
+            return Err(self.error(
+                op_start,
+                self.heap.string(name).to_string(),
+                RuntimeErrorType::UndefinedProperty,
+            ));
+        };
+
+        let native = self.alloc(Object::NativeMethod(NativeMethod {
+            receiver: obj,
+            method,
+        }));
+        Ok(Some(Value::Obj(native)))
+    }
+
+    // Runs a list method on the arguments at the top of the stack, which have
+    // already been checked against its arity. The list stays reachable through
+    // the method in the callee's slot.
+    fn call_list_method(
+        &mut self,
+        list: ObjRef,
+        method: ListMethod,
+        op_start: usize,
+    ) -> Result<Value, RuntimeError> {
+        match method {
+            ListMethod::Push => {
+                let value = self.peek(0);
+                self.heap.list_mut(list).push(value);
+                Ok(Value::Nil)
+            }
+            ListMethod::Pop => {
+                let popped = self.heap.list_mut(list).pop();
+                popped.ok_or_else(|| {
+                    self.error(
+                        op_start,
+                        String::from("pop from an empty list"),
+                        RuntimeErrorType::IndexOutOfBounds,
+                    )
+                })
+            }
+            ListMethod::Len => Ok(Value::Number(self.heap.list(list).len() as f64)),
+            ListMethod::Insert => {
+                let (index, value) = (self.peek(1), self.peek(0));
+                let position = self.list_position(list, index, true, op_start)?;
+                self.heap.list_mut(list).insert(position, value);
+                Ok(Value::Nil)
+            }
+            ListMethod::Remove => {
+                let position = self.list_position(list, self.peek(0), false, op_start)?;
+                Ok(self.heap.list_mut(list).remove(position))
+            }
+            ListMethod::Slice => {
+                let bound = |value: Value| match value {
+                    Value::Number(n) => Ok(Some(n)),
+                    Value::Nil => Ok(None),
+                    value => Err(self.error(
+                        op_start,
+                        self.heap.format_value(value),
+                        RuntimeErrorType::InvalidIndex,
+                    )),
+                };
+                let (start, end) = (bound(self.peek(1))?, bound(self.peek(0))?);
+                let elements = self.heap.list(list);
+                let range = lox_list::slice_range(start.unwrap_or(0.0), end, elements.len())
+                    .map_err(|(error_type, cause)| self.error(op_start, cause, error_type))?;
+                let slice = elements[range].to_vec();
+                Ok(Value::Obj(self.alloc(Object::List(slice))))
+            }
+        }
+    }
+
+    // like `call_list_method`, for maps
+    fn call_map_method(
+        &mut self,
+        map: ObjRef,
+        method: MapMethod,
+        op_start: usize,
+    ) -> Result<Value, RuntimeError> {
+        match method {
+            MapMethod::Keys => {
+                let keys = self
+                    .heap
+                    .map(map)
+                    .iter()
+                    .map(|(key, _)| heap::key_value(key));
+                let keys = keys.collect();
+                Ok(Value::Obj(self.alloc(Object::List(keys))))
+            }
+            MapMethod::Values => {
+                let values = self.heap.map(map).iter().map(|(_, value)| *value).collect();
+                Ok(Value::Obj(self.alloc(Object::List(values))))
+            }
+            MapMethod::Has => {
+                let key = self.map_key(self.peek(0), op_start)?;
+                Ok(Value::Bool(self.heap.map(map).contains_key(&key)))
+            }
+            // removing a key that isn't there does nothing
+            MapMethod::Remove => {
+                let key = self.map_key(self.peek(0), op_start)?;
+                Ok(self.heap.map_mut(map).remove(&key).unwrap_or(Value::Nil))
+            }
+        }
+    }
+
+    fn not_indexable(&self, object: Value, op_start: usize) -> RuntimeError {
+        self.error(
+            op_start,
+            self.heap.format_value(object),
+            RuntimeErrorType::NotIndexable,
+        )
+    }
+
+    fn map_key(&self, value: Value, op_start: usize) -> Result<MapKey<ObjRef>, RuntimeError> {
+        match value {
+            Value::Nil => Ok(MapKey::Nil),
+            Value::Bool(b) => Ok(MapKey::Bool(b)),
+            Value::Number(n) => Ok(MapKey::number(n)),
+            Value::Obj(s) if matches!(self.heap.get(s), Object::String(_)) => Ok(MapKey::Str(s)),
+            value => Err(self.error(
+                op_start,
+                self.heap.format_value(value),
+                RuntimeErrorType::UnhashableKey,
+            )),
+        }
+    }
+
+    fn list_position(
+        &self,
+        list: ObjRef,
+        index: Value,
+        past_end: bool,
+        op_start: usize,
+    ) -> Result<usize, RuntimeError> {
+        let Value::Number(index) = index else {
+            return Err(self.error(
+                op_start,
+                self.heap.format_value(index),
+                RuntimeErrorType::InvalidIndex,
+            ));
+        };
+        lox_list::position(index, self.heap.list(list).len(), past_end)
+            .map_err(|(error_type, cause)| self.error(op_start, cause, error_type))
+    }
+
+    fn add(&mut self, op_start: usize) -> Result<(), RuntimeError> {
+        let (a, b) = self.pop_pair();
+        let value = match (a, b) {
+            (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+            (Value::Obj(a), Value::Obj(b)) => match (self.heap.get(a), self.heap.get(b)) {
+                (Object::String(a), Object::String(b)) => {
+                    let concatenated = Symbol::intern(&format!("{}{}", a, b));
+                    Value::Obj(self.intern(concatenated))
+                }
+                _ => return Err(self.add_error(op_start)),
+            },
+            _ => return Err(self.add_error(op_start)),
+        };
+        self.push(value);
+        Ok(())
+    }
+
+    fn add_error(&self, op_start: usize) -> RuntimeError {
+        self.error(
+            op_start,
+            String::from("+"),
+            RuntimeErrorType::OperandMustBeNumberOrString,
+        )
+    }
+
+    // reuses the open upvalue for a slot so every closure sees the same variable
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        let position = self
+            .open_upvalues
+            .partition_point(|upvalue| self.open_slot(*upvalue) < slot);
+
+        if let Some(existing) = self.open_upvalues.get(position) {
+            if self.open_slot(*existing) == slot {
+                return *existing;
+            }
+        }
+
+        let upvalue = self.alloc(Object::Upvalue(Upvalue::Open(slot)));
+        self.open_upvalues.insert(position, upvalue);
+        upvalue
+    }
+
+    // moves every variable at or above `last` off the stack and into its upvalue
+    fn close_upvalues(&mut self, last: usize) {
+        let position = self
+            .open_upvalues
+            .partition_point(|upvalue| self.open_slot(*upvalue) < last);
+
+        for upvalue in self.open_upvalues.split_off(position) {
+            let slot = self.open_slot(upvalue);
+            *self.heap.upvalue_mut(upvalue) = Upvalue::Closed(self.stack[slot]);
+        }
+    }
+
+    fn open_slot(&self, upvalue: ObjRef) -> usize {
+        match self.heap.upvalue(upvalue) {
+            Upvalue::Open(slot) => *slot,
+            Upvalue::Closed(_) => unreachable!("closed upvalues are never in the open list"),
+        }
+    }
+
+    fn as_instance(&self, value: Value) -> Option<ObjRef> {
+        match value {
+            Value::Obj(obj) if matches!(self.heap.get(obj), Object::Instance(_)) => Some(obj),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self, value: Value) -> Option<ObjRef> {
+        match value {
+            Value::Obj(obj) if matches!(self.heap.get(obj), Object::List(_)) => Some(obj),
+            _ => None,
+        }
+    }
+
+    fn as_map(&self, value: Value) -> Option<ObjRef> {
+        match value {
+            Value::Obj(obj) if matches!(self.heap.get(obj), Object::Map(_)) => Some(obj),
+            _ => None,
+        }
+    }
+
+    fn as_class(&self, value: Value) -> Option<ObjRef> {
+        match value {
+            Value::Obj(obj) if matches!(self.heap.get(obj), Object::Class(_)) => Some(obj),
+            _ => None,
+        }
+    }
+
+    fn pop_pair(&mut self) -> (Value, Value) {
+        let b = self.pop();
+        let a = self.pop();
+        (a, b)
+    }
+
+    fn pop_numbers(&mut self, op: OpCode, op_start: usize) -> Result<(f64, f64), RuntimeError> {
+        match self.pop_pair() {
+            (Value::Number(a), Value::Number(b)) => Ok((a, b)),
+            _ => Err(self.error(
+                op_start,
+                String::from(operator_lexeme(op)),
+                RuntimeErrorType::OperandMustBeNumber,
+            )),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("call frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("call frame")
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame_mut();
+        let value = frame.function.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_u16() as usize;
+        self.frame().function.chunk.constants[index]
+    }
+
+    fn read_name(&mut self) -> ObjRef {
+        match self.read_constant() {
+            Value::Obj(obj) => obj,
+            _ => unreachable!("name operands are always strings"),
+        }
+    }
+
+    fn read_upvalue(&mut self) -> ObjRef {
+        let index = self.read_byte() as usize;
+        self.heap.closure(self.frame().closure).upvalues[index]
+    }
+
+    // errors point at the source of the instruction that raised them
+    fn error(&self, op_start: usize, cause: String, error_type: RuntimeErrorType) -> RuntimeError {
+        let span: Span = self.frame().function.chunk.spans[op_start];
+        RuntimeError::new(span, cause, error_type).in_source(&self.frame().function.source)
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn operator_lexeme(op: OpCode) -> &'static str {
+    match op {
+        OpCode::Greater => ">",
+        OpCode::GreaterEqual => ">=",
+        OpCode::Less => "<",
+        OpCode::LessEqual => "<=",
+        OpCode::Add => "+",
+        OpCode::Multiply => "*",
+        OpCode::Divide => "/",
+        _ => "-",
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    mem,
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    bytecode::heap::GcConfig, environment::Environment, lox_class::LoxClass,
+    lox_function::LoxFunction, lox_instance::LoxInstance, lox_list::LoxList, lox_map::LoxMap,
+    lox_value::LoxValue,
+};
+
+// Frees the tree-walker's reference cycles, which `Rc` alone never does: a
+// closure kept in a list its own environment holds, an instance stored in one
+// of its fields, and so on.
+//
+// Every environment, function, class, instance, list and map the interpreter
+// creates is tracked here. A collection works out how many references to each
+// of them come from somewhere other than another tracked object, as CPython's
+// collector does. Objects with any are in use, whether by the globals, a call
+// in progress, the Rust stack or a native function, and so is everything they
+// refer to. The rest can only be reached from each other, so they are emptied
+// to break their cycles and `Rc` frees them.
+pub struct Collector {
+    objects: Vec<Tracked>,
+    config: GcConfig,
+    bytes_allocated: usize, // by the tracked objects themselves, not what they own
+    next_gc: usize,
+}
+
+impl Collector {
+    pub(crate) fn new(config: GcConfig) -> Self {
+        Self {
+            objects: vec![],
+            config,
+            bytes_allocated: 0,
+            next_gc: config.initial_threshold,
+        }
+    }
+
+    pub(crate) fn environment(&mut self, environment: Environment) -> Rc<RefCell<Environment>> {
+        let environment = Rc::new(RefCell::new(environment));
+        self.track(Tracked::Environment(Rc::downgrade(&environment)));
+        environment
+    }
+
+    pub(crate) fn function(&mut self, function: LoxFunction) -> Rc<LoxFunction> {
+        let function = Rc::new(function);
+        self.track(Tracked::Function(Rc::downgrade(&function)));
+        function
+    }
+
+    pub(crate) fn class(&mut self, class: LoxClass) -> Rc<LoxClass> {
+        let class = Rc::new(class);
+        self.track(Tracked::Class(Rc::downgrade(&class)));
+        class
+    }
+
+    pub(crate) fn instance(&mut self, instance: LoxInstance) -> Rc<RefCell<LoxInstance>> {
+        let instance = Rc::new(RefCell::new(instance));
+        self.track(Tracked::Instance(Rc::downgrade(&instance)));
+        instance
+    }
+
+    pub(crate) fn list(&mut self, list: LoxList) -> Rc<RefCell<LoxList>> {
+        let list = Rc::new(RefCell::new(list));
+        self.track(Tracked::List(Rc::downgrade(&list)));
+        list
+    }
+
+    pub(crate) fn map(&mut self, map: LoxMap) -> Rc<RefCell<LoxMap>> {
+        let map = Rc::new(RefCell::new(map));
+        self.track(Tracked::Map(Rc::downgrade(&map)));
+        map
+    }
+
+    // the object being tracked is held by the caller, so a collection can't free it
+    fn track(&mut self, tracked: Tracked) {
+        if self.config.stress || self.bytes_allocated > self.next_gc {
+            self.collect();
+        }
+        self.bytes_allocated += tracked.size();
+        self.objects.push(tracked);
+    }
+
+    pub(crate) fn collect(&mut self) {
+        // holding on to every tracked object still alive keeps them all in
+        // place until the collection is over
+        let objects: Vec<Object> = self.objects.iter().filter_map(Tracked::upgrade).collect();
+        let index: HashMap<*const (), usize> = objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| (object.address(), i))
+            .collect();
+
+        // the tracked objects object `i` refers to are `references[starts[i]..starts[i + 1]]`
+        let mut references = vec![];
+        let mut starts = Vec::with_capacity(objects.len() + 1);
+        let mut borrowed = Vec::with_capacity(objects.len());
+        let mut addresses = vec![];
+        for object in &objects {
+            starts.push(references.len());
+            addresses.clear();
+            borrowed.push(!object.references(&mut addresses));
+            references.extend(addresses.iter().filter_map(|a| index.get(a).copied()));
+        }
+        starts.push(references.len());
+
+        // what is left of each count without the references from other tracked
+        // objects and the one in `objects` comes from elsewhere
+        let mut outside: Vec<usize> = objects.iter().map(|o| o.strong_count() - 1).collect();
+        for &target in &references {
+            outside[target] -= 1;
+        }
+
+        // objects that couldn't be looked into are in use too
+        let mut live = vec![false; objects.len()];
+        let mut pending: Vec<usize> = (0..objects.len())
+            .filter(|&i| outside[i] > 0 || borrowed[i])
+            .collect();
+        while let Some(i) = pending.pop() {
+            if !live[i] {
+                live[i] = true;
+                pending.extend(&references[starts[i]..starts[i + 1]]);
+            }
+        }
+
+        self.objects.clear();
+        self.bytes_allocated = 0;
+        for (object, live) in objects.iter().zip(live) {
+            if live {
+                let tracked = object.downgrade();
+                self.bytes_allocated += tracked.size();
+                self.objects.push(tracked);
+            } else {
+                object.clear();
+            }
+        }
+        self.next_gc =
+            (self.bytes_allocated * self.config.growth_factor).max(self.config.initial_threshold);
+    }
+}
+
+enum Tracked {
+    Environment(Weak<RefCell<Environment>>),
+    Function(Weak<LoxFunction>),
+    Class(Weak<LoxClass>),
+    Instance(Weak<RefCell<LoxInstance>>),
+    List(Weak<RefCell<LoxList>>),
+    Map(Weak<RefCell<LoxMap>>),
+}
+
+impl Tracked {
+    fn upgrade(&self) -> Option<Object> {
+        match self {
+            Tracked::Environment(weak) => weak.upgrade().map(Object::Environment),
+            Tracked::Function(weak) => weak.upgrade().map(Object::Function),
+            Tracked::Class(weak) => weak.upgrade().map(Object::Class),
+            Tracked::Instance(weak) => weak.upgrade().map(Object::Instance),
+            Tracked::List(weak) => weak.upgrade().map(Object::List),
+            Tracked::Map(weak) => weak.upgrade().map(Object::Map),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Tracked::Environment(_) => mem::size_of::<RefCell<Environment>>(),
+            Tracked::Function(_) => mem::size_of::<LoxFunction>(),
+            Tracked::Class(_) => mem::size_of::<LoxClass>(),
+            Tracked::Instance(_) => mem::size_of::<RefCell<LoxInstance>>(),
+            Tracked::List(_) => mem::size_of::<RefCell<LoxList>>(),
+            Tracked::Map(_) => mem::size_of::<RefCell<LoxMap>>(),
+        }
+    }
+}
+
+// a tracked object, held on to for the length of a collection
+enum Object {
+    Environment(Rc<RefCell<Environment>>),
+    Function(Rc<LoxFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    List(Rc<RefCell<LoxList>>),
+    Map(Rc<RefCell<LoxMap>>),
+}
+
+impl Object {
+    fn downgrade(&self) -> Tracked {
+        match self {
+            Object::Environment(rc) => Tracked::Environment(Rc::downgrade(rc)),
+            Object::Function(rc) => Tracked::Function(Rc::downgrade(rc)),
+            Object::Class(rc) => Tracked::Class(Rc::downgrade(rc)),
+            Object::Instance(rc) => Tracked::Instance(Rc::downgrade(rc)),
+            Object::List(rc) => Tracked::List(Rc::downgrade(rc)),
+            Object::Map(rc) => Tracked::Map(Rc::downgrade(rc)),
+        }
+    }
+
+    fn address(&self) -> *const () {
+        match self {
+            Object::Environment(rc) => Rc::as_ptr(rc) as *const (),
+            Object::Function(rc) => Rc::as_ptr(rc) as *const (),
+            Object::Class(rc) => Rc::as_ptr(rc) as *const (),
+            Object::Instance(rc) => Rc::as_ptr(rc) as *const (),
+            Object::List(rc) => Rc::as_ptr(rc) as *const (),
+            Object::Map(rc) => Rc::as_ptr(rc) as *const (),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Object::Environment(rc) => Rc::strong_count(rc),
+            Object::Function(rc) => Rc::strong_count(rc),
+            Object::Class(rc) => Rc::strong_count(rc),
+            Object::Instance(rc) => Rc::strong_count(rc),
+            Object::List(rc) => Rc::strong_count(rc),
+            Object::Map(rc) => Rc::strong_count(rc),
+        }
+    }
+
+    // Adds the addresses of the objects this one holds a reference to, once
+    // for each reference. False if it is borrowed and can't be looked into.
+    fn references(&self, references: &mut Vec<*const ()>) -> bool {
+        match self {
+            Object::Environment(environment) => {
+                let Ok(environment) = environment.try_borrow() else {
+                    return false;
+                };
+                if let Some(enclosing) = environment.enclosing() {
+                    references.push(Rc::as_ptr(&enclosing) as *const ());
+                }
+                references.extend(environment.values().filter_map(value_address));
+            }
+            Object::Function(function) => {
+                references.push(Rc::as_ptr(function.closure()) as *const ());
+            }
+            Object::Class(class) => {
+                if let Some(superclass) = class.superclass() {
+                    references.push(Rc::as_ptr(superclass) as *const ());
+                }
+                references.extend(class.methods().map(|m| Rc::as_ptr(m) as *const ()));
+            }
+            Object::Instance(instance) => {
+                let Ok(instance) = instance.try_borrow() else {
+                    return false;
+                };
+                references.push(Rc::as_ptr(instance.class()) as *const ());
+                references.extend(instance.fields().filter_map(value_address));
+            }
+            Object::List(list) => {
+                let Ok(list) = list.try_borrow() else {
+                    return false;
+                };
+                references.extend(list.elements().iter().filter_map(value_address));
+            }
+            Object::Map(map) => {
+                let Ok(map) = map.try_borrow() else {
+                    return false;
+                };
+                references.extend(map.entries().iter().filter_map(|(_, v)| value_address(v)));
+            }
+        }
+        true
+    }
+
+    // lets go of everything the object holds, breaking the cycles it is part of
+    fn clear(&self) {
+        match self {
+            Object::Environment(environment) => environment.borrow_mut().clear(),
+            Object::Instance(instance) => instance.borrow_mut().clear(),
+            Object::List(list) => list.borrow_mut().clear(),
+            Object::Map(map) => map.borrow_mut().clear(),
+            // every cycle passes through one of the others
+            Object::Function(_) | Object::Class(_) => {}
+        }
+    }
+}
+
+// Native functions aren't tracked, so whatever they capture looks held from
+// elsewhere and stays alive as long as they do.
+fn value_address(value: &LoxValue) -> Option<*const ()> {
+    match value {
+        LoxValue::Function(function) => Some(Rc::as_ptr(function) as *const ()),
+        LoxValue::Class(class) => Some(Rc::as_ptr(class) as *const ()),
+        LoxValue::Instance(instance) => Some(Rc::as_ptr(instance) as *const ()),
+        LoxValue::List(list) => Some(Rc::as_ptr(list) as *const ()),
+        LoxValue::Map(map) => Some(Rc::as_ptr(map) as *const ()),
+        LoxValue::Nil
+        | LoxValue::Bool(_)
+        | LoxValue::Number(_)
+        | LoxValue::Str(_)
+        | LoxValue::NativeFunction(_) => None,
+    }
+}
@@ -0,0 +1,435 @@
+use std::{cell::RefCell, io, path::Path, process, rc::Rc};
+
+use rust_lox::{
+    bytecode::heap::GcConfig,
+    debugger::{DebugFrontend, Debugger, Frame, Paused, Resume, StopReason},
+    LoxError, LoxValue,
+};
+
+use crate::{
+    json::{self, Json},
+    runner,
+};
+
+// Lox programs only ever have the one thread.
+const THREAD_ID: u32 = 1;
+
+// Sends protocol messages on stdout, numbering them as it goes.
+#[derive(Default)]
+struct Connection {
+    seq: u32,
+}
+
+impl Connection {
+    fn send(&mut self, kind: &str, mut members: Vec<(String, Json)>) {
+        self.seq += 1;
+        members.insert(0, (String::from("seq"), self.seq.into()));
+        members.insert(1, (String::from("type"), kind.into()));
+        // stdout going away means the editor hung up
+        if json::write_message(&mut io::stdout(), &Json::Object(members)).is_err() {
+            process::exit(0);
+        }
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) {
+        self.send(
+            "response",
+            vec![
+                (String::from("request_seq"), request.get("seq").clone()),
+                (String::from("success"), true.into()),
+                (String::from("command"), request.get("command").clone()),
+                (String::from("body"), body),
+            ],
+        );
+    }
+
+    fn respond_error(&mut self, request: &Json, message: &str) {
+        self.send(
+            "response",
+            vec![
+                (String::from("request_seq"), request.get("seq").clone()),
+                (String::from("success"), false.into()),
+                (String::from("command"), request.get("command").clone()),
+                (String::from("message"), message.into()),
+            ],
+        );
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.send(
+            "event",
+            vec![
+                (String::from("event"), event.into()),
+                (String::from("body"), body),
+            ],
+        );
+    }
+}
+
+// The next request, or `None` once the editor has gone. Messages that can't
+// be understood are reported on the editor's console and skipped.
+fn read_request(connection: &mut Connection) -> Option<Json> {
+    loop {
+        match json::read_message(&mut io::stdin().lock()) {
+            Ok(request) => return request,
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                let message = format!("Malformed message: {}", e);
+                eprintln!("{}", message);
+                connection.event("output", output_body("console", &message));
+            }
+            Err(e) => {
+                eprintln!("Failed to read message: {}", e);
+                return None;
+            }
+        }
+    }
+}
+
+// Serves one debug session over stdin and stdout: the editor configures and
+// launches a script, which then runs on the tree-walker with a debugger that
+// reports each stop back to the editor.
+pub fn run() {
+    let connection = Rc::new(RefCell::new(Connection::default()));
+    let mut program = None;
+    let mut stop_on_entry = false;
+    let mut breakpoints: Vec<(String, Vec<u32>)> = vec![];
+
+    loop {
+        let mut connection = connection.borrow_mut();
+        let Some(request) = read_request(&mut connection) else {
+            return;
+        };
+        match request.get("command").as_str().unwrap_or_default() {
+            "initialize" => {
+                let capabilities = Json::object([
+                    ("supportsConfigurationDoneRequest", true.into()),
+                    ("supportsEvaluateForHovers", true.into()),
+                ]);
+                connection.respond(&request, capabilities);
+                connection.event("initialized", Json::object([]));
+            }
+            "launch" => {
+                let arguments = request.get("arguments");
+                match arguments.get("program").as_str() {
+                    Some(path) => {
+                        program = Some(String::from(path));
+                        stop_on_entry = arguments.get("stopOnEntry").as_bool().unwrap_or(false);
+                        connection.respond(&request, Json::object([]));
+                    }
+                    None => connection.respond_error(&request, "Missing 'program' to launch"),
+                }
+            }
+            "setBreakpoints" => {
+                let (file, lines) = requested_breakpoints(&request);
+                connection.respond(&request, breakpoints_body(&lines));
+                breakpoints.retain(|(f, _)| *f != file);
+                breakpoints.push((file, lines));
+            }
+            "setExceptionBreakpoints" => connection.respond(&request, Json::object([])),
+            "threads" => connection.respond(&request, threads_body()),
+            "configurationDone" => {
+                connection.respond(&request, Json::object([]));
+                break;
+            }
+            "disconnect" | "terminate" => {
+                connection.respond(&request, Json::object([]));
+                return;
+            }
+            command => connection.respond_error(&request, &unsupported(command)),
+        }
+    }
+
+    let Some(program) = program else {
+        connection
+            .borrow_mut()
+            .event("terminated", Json::object([]));
+        return;
+    };
+
+    let mut debugger = Debugger::new(Box::new(DapFrontend {
+        connection: Rc::clone(&connection),
+        stop_on_entry,
+    }));
+    for (file, lines) in &breakpoints {
+        for line in lines {
+            debugger.set_breakpoint(file, *line);
+        }
+    }
+    let output = Rc::clone(&connection);
+    debugger.redirect_output(Box::new(move |text| {
+        output
+            .borrow_mut()
+            .event("output", output_body("stdout", text))
+    }));
+
+    let mut interpreter = runner::interpreter(GcConfig::default());
+    interpreter.attach_debugger(debugger);
+    let exit_code: u32 = match interpreter.run_file(&program) {
+        Ok(_) => 0,
+        Err(e) => {
+            connection
+                .borrow_mut()
+                .event("output", output_body("stderr", &e.to_string()));
+            match e {
+                LoxError::Compile(_) => 65,
+                LoxError::Runtime(_) => 70,
+                LoxError::Io(_) => 99,
+            }
+        }
+    };
+
+    let mut connection = connection.borrow_mut();
+    connection.event(
+        "exited",
+        Json::object([("exitCode", Json::from(exit_code))]),
+    );
+    connection.event("terminated", Json::object([]));
+
+    // the editor still gets answers until it disconnects
+    while let Some(request) = read_request(&mut connection) {
+        match request.get("command").as_str().unwrap_or_default() {
+            "disconnect" | "terminate" => {
+                connection.respond(&request, Json::object([]));
+                return;
+            }
+            "threads" => connection.respond(&request, threads_body()),
+            _ => connection.respond_error(&request, "The program has finished"),
+        }
+    }
+}
+
+struct DapFrontend {
+    connection: Rc<RefCell<Connection>>,
+    stop_on_entry: bool,
+}
+
+impl DebugFrontend for DapFrontend {
+    fn paused(&mut self, paused: &mut Paused) -> Resume {
+        let reason = match paused.reason() {
+            StopReason::Entry if !self.stop_on_entry => return Resume::Continue,
+            StopReason::Entry => "entry",
+            StopReason::Breakpoint => "breakpoint",
+            StopReason::Step => "step",
+        };
+        self.connection.borrow_mut().event(
+            "stopped",
+            Json::object([
+                ("reason", reason.into()),
+                ("threadId", THREAD_ID.into()),
+                ("allThreadsStopped", true.into()),
+            ]),
+        );
+
+        loop {
+            let Some(request) = read_request(&mut self.connection.borrow_mut()) else {
+                process::exit(0);
+            };
+            let resume = match request.get("command").as_str().unwrap_or_default() {
+                "continue" => Some(Resume::Continue),
+                "next" => Some(Resume::StepOver),
+                "stepIn" => Some(Resume::StepInto),
+                "stepOut" => Some(Resume::StepOut),
+                _ => None,
+            };
+            if let Some(resume) = resume {
+                let body = Json::object([("allThreadsContinued", true.into())]);
+                self.connection.borrow_mut().respond(&request, body);
+                return resume;
+            }
+            self.handle(paused, &request);
+        }
+    }
+}
+
+impl DapFrontend {
+    // answers a request that leaves the program paused
+    fn handle(&mut self, paused: &mut Paused, request: &Json) {
+        let arguments = request.get("arguments");
+        let result = match request.get("command").as_str().unwrap_or_default() {
+            "threads" => Ok(threads_body()),
+            "stackTrace" => Ok(stack_trace_body(paused)),
+            "scopes" => frame(paused, arguments.get("frameId")).map(|_| {
+                let id = arguments.get("frameId").as_u32().unwrap_or(0);
+                Json::object([(
+                    "scopes",
+                    Json::Array(vec![
+                        scope("Locals", id * 2 + 1),
+                        scope("Globals", id * 2 + 2),
+                    ]),
+                )])
+            }),
+            "variables" => variables_body(paused, arguments.get("variablesReference")),
+            "evaluate" => frame_number(arguments.get("frameId")).and_then(|id| {
+                let expression = arguments.get("expression").as_str().unwrap_or_default();
+                match paused.evaluate(id, expression) {
+                    Some(Ok(value)) => Ok(Json::object([
+                        ("result", describe(&value).into()),
+                        ("variablesReference", 0u32.into()),
+                    ])),
+                    Some(Err(e)) => Err(e.to_string()),
+                    None => Err(format!("No frame {}", id)),
+                }
+            }),
+            "setBreakpoints" => {
+                let (file, lines) = requested_breakpoints(request);
+                paused.clear_breakpoints(&file);
+                for line in &lines {
+                    paused.set_breakpoint(&file, *line);
+                }
+                Ok(breakpoints_body(&lines))
+            }
+            "setExceptionBreakpoints" => Ok(Json::object([])),
+            "disconnect" | "terminate" => {
+                self.connection
+                    .borrow_mut()
+                    .respond(request, Json::object([]));
+                process::exit(0);
+            }
+            command => Err(unsupported(command)),
+        };
+
+        let mut connection = self.connection.borrow_mut();
+        match result {
+            Ok(body) => connection.respond(request, body),
+            Err(message) => connection.respond_error(request, &message),
+        }
+    }
+}
+
+// frames are numbered from the innermost call, which is also the default
+fn frame_number(id: &Json) -> Result<usize, String> {
+    if *id == Json::Null {
+        return Ok(0);
+    }
+    let id = id.as_u32().ok_or("Invalid frame id")?;
+    Ok(id as usize)
+}
+
+fn frame<'a>(paused: &'a Paused, id: &Json) -> Result<&'a Frame, String> {
+    let id = frame_number(id)?;
+    paused
+        .frames()
+        .nth(id)
+        .ok_or_else(|| format!("No frame {}", id))
+}
+
+fn stack_trace_body(paused: &Paused) -> Json {
+    let source = source(paused.file());
+    let frames: Vec<Json> = paused
+        .frames()
+        .enumerate()
+        .map(|(id, frame)| {
+            Json::object([
+                ("id", id.into()),
+                ("name", frame.name.as_str().into()),
+                ("source", source.clone()),
+                ("line", frame.line.into()),
+                ("column", 1u32.into()),
+            ])
+        })
+        .collect();
+    let total = frames.len();
+    Json::object([
+        ("stackFrames", frames.into()),
+        ("totalFrames", total.into()),
+    ])
+}
+
+fn scope(name: &str, reference: u32) -> Json {
+    Json::object([
+        ("name", name.into()),
+        ("variablesReference", reference.into()),
+        ("expensive", false.into()),
+    ])
+}
+
+// Each frame has two scopes: its locals, with shadowed names left out, and
+// the globals. References are numbered from the frame id to find them again.
+fn variables_body(paused: &Paused, reference: &Json) -> Result<Json, String> {
+    let reference = reference
+        .as_u32()
+        .filter(|r| *r > 0)
+        .ok_or("Invalid variables reference")?;
+    let frame_id = Json::from((reference - 1) / 2);
+    let mut scopes = frame(paused, &frame_id)?.scopes();
+    let globals = scopes.pop().unwrap_or_default();
+
+    let bindings = if reference % 2 == 0 {
+        globals
+    } else {
+        let mut locals: Vec<_> = vec![];
+        for (name, value) in scopes.into_iter().flatten() {
+            if !locals.iter().any(|(n, _)| *n == name) {
+                locals.push((name, value));
+            }
+        }
+        locals
+    };
+
+    let variables: Vec<Json> = bindings
+        .iter()
+        .map(|(name, value)| {
+            Json::object([
+                ("name", name.as_str().into()),
+                ("value", describe(value).into()),
+                ("variablesReference", 0u32.into()),
+            ])
+        })
+        .collect();
+    Ok(Json::object([("variables", variables.into())]))
+}
+
+// strings are quoted so they can be told apart from other values
+fn describe(value: &LoxValue) -> String {
+    match value {
+        LoxValue::Str(s) => format!("{:?}", s),
+        value => value.to_string(),
+    }
+}
+
+fn source(path: &str) -> Json {
+    let name = Path::new(path)
+        .file_name()
+        .map_or_else(|| String::from(path), |n| n.to_string_lossy().into_owned());
+    Json::object([("name", name.into()), ("path", path.into())])
+}
+
+fn requested_breakpoints(request: &Json) -> (String, Vec<u32>) {
+    let arguments = request.get("arguments");
+    let file = arguments
+        .get("source")
+        .get("path")
+        .as_str()
+        .unwrap_or_default();
+    let lines = arguments
+        .get("breakpoints")
+        .as_array()
+        .iter()
+        .filter_map(|b| b.get("line").as_u32())
+        .collect();
+    (String::from(file), lines)
+}
+
+fn breakpoints_body(lines: &[u32]) -> Json {
+    let breakpoints: Vec<Json> = lines
+        .iter()
+        .map(|line| Json::object([("verified", true.into()), ("line", (*line).into())]))
+        .collect();
+    Json::object([("breakpoints", breakpoints.into())])
+}
+
+fn threads_body() -> Json {
+    let thread = Json::object([("id", THREAD_ID.into()), ("name", "main".into())]);
+    Json::object([("threads", Json::Array(vec![thread]))])
+}
+
+fn output_body(category: &str, text: &str) -> Json {
+    Json::object([
+        ("category", category.into()),
+        ("output", format!("{}\n", text).into()),
+    ])
+}
+
+fn unsupported(command: &str) -> String {
+    format!("Unsupported request '{}'", command)
+}
@@ -0,0 +1,146 @@
+use std::{
+    io::{self, Write},
+    process,
+};
+
+use rust_lox::debugger::{DebugFrontend, Paused, Resume, StopReason};
+
+const HELP: &str = "\
+break [file:]line     stop when the line is reached      (b)
+delete [file:]line    remove a breakpoint                (d)
+breakpoints           list breakpoints
+step                  step into calls                    (s)
+next                  step over calls                    (n)
+finish                run until the current call returns (f)
+continue              run until the next breakpoint      (c)
+stack                 show the call stack                (bt)
+env                   show every variable in scope
+print <expr>          evaluate an expression here        (p)
+quit                  stop the program                   (q)";
+
+// Reads debugger commands from stdin whenever the program stops.
+#[derive(Default)]
+pub struct DebugPrompt {
+    detached: bool, // stdin closed, so the program runs to the end
+}
+
+impl DebugFrontend for DebugPrompt {
+    fn paused(&mut self, paused: &mut Paused) -> Resume {
+        if self.detached {
+            return Resume::Continue;
+        }
+
+        let reason = match paused.reason() {
+            StopReason::Entry => "Paused on entry",
+            StopReason::Breakpoint => "Hit breakpoint",
+            StopReason::Step => "Stepped",
+        };
+        println!(
+            "{} in {} at {}:{}",
+            reason,
+            paused.top_frame().name,
+            paused.file(),
+            paused.line()
+        );
+        print_line(paused, paused.line());
+
+        let stdin = io::stdin();
+        let mut input = String::new();
+        loop {
+            print!("(debug) ");
+            let _ = io::stdout().flush();
+
+            input.clear();
+            match stdin.read_line(&mut input) {
+                // nobody left to ask, let the program finish
+                Ok(0) | Err(_) => {
+                    self.detached = true;
+                    return Resume::Continue;
+                }
+                Ok(_) => {}
+            }
+
+            let input = input.trim();
+            let (command, argument) = match input.split_once(char::is_whitespace) {
+                Some((command, argument)) => (command, argument.trim()),
+                None => (input, ""),
+            };
+
+            match command {
+                "" => {}
+                "s" | "step" => return Resume::StepInto,
+                "n" | "next" => return Resume::StepOver,
+                "f" | "finish" => return Resume::StepOut,
+                "c" | "continue" => return Resume::Continue,
+                "q" | "quit" => process::exit(0),
+                "b" | "break" => match location(paused, argument) {
+                    Some((file, line)) => {
+                        paused.set_breakpoint(&file, line);
+                        println!("Breakpoint set at {}:{}", file, line);
+                    }
+                    None => println!("Usage: break [file:]line"),
+                },
+                "d" | "delete" => match location(paused, argument) {
+                    Some((file, line)) => {
+                        if !paused.clear_breakpoint(&file, line) {
+                            println!("No breakpoint at {}:{}", file, line);
+                        }
+                    }
+                    None => println!("Usage: delete [file:]line"),
+                },
+                "breakpoints" => {
+                    for (file, line) in paused.breakpoints() {
+                        println!("{}:{}", file, line);
+                    }
+                }
+                "bt" | "stack" => {
+                    for (depth, frame) in paused.frames().enumerate() {
+                        println!(
+                            "#{} {} at {}:{}",
+                            depth,
+                            frame.name,
+                            paused.file(),
+                            frame.line
+                        );
+                    }
+                }
+                "env" => {
+                    let scopes = paused.top_frame().scopes();
+                    let globals = scopes.len() - 1;
+                    for (depth, scope) in scopes.iter().enumerate() {
+                        if depth == globals {
+                            println!("globals:");
+                        } else {
+                            println!("scope {}:", depth);
+                        }
+                        for (name, value) in scope {
+                            println!("  {} = {}", name, value);
+                        }
+                    }
+                }
+                "p" | "print" => match paused.evaluate(0, argument) {
+                    Some(Ok(value)) => println!("{}", value),
+                    Some(Err(e)) => println!("{}", e),
+                    None => unreachable!("the script frame is never popped"),
+                },
+                "h" | "help" => println!("{}", HELP),
+                _ => println!("Unknown command '{}', try 'help'", command),
+            }
+        }
+    }
+}
+
+fn print_line(paused: &Paused, line: u32) {
+    if let Some(text) = paused.source_line(line) {
+        println!("{:>5} | {}", line, text);
+    }
+}
+
+// `line` or `file:line`, defaulting to the script being debugged
+fn location(paused: &Paused, argument: &str) -> Option<(String, u32)> {
+    let (file, line) = match argument.rsplit_once(':') {
+        Some((file, line)) => (String::from(file), line),
+        None => (String::from(paused.file()), argument),
+    };
+    Some((file, line.parse().ok()?))
+}
@@ -0,0 +1,324 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::Path,
+    rc::Rc,
+};
+
+use crate::{
+    environment::Environment,
+    errors::{Diagnostics, LoxError},
+    frontend,
+    interpretable::Interpretable,
+    lox_value::LoxValue,
+    parser::Parser,
+    resolver::Resolver,
+    runtime::Runtime,
+    symbol::Symbol,
+    token::Span,
+};
+
+// why the program stopped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+// how to carry on once the front end is done inspecting a paused program
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resume {
+    Continue,
+    StepInto,
+    StepOver,
+    StepOut,
+}
+
+// Receives each line the program prints.
+pub type OutputSink = Box<dyn FnMut(&str)>;
+
+// Talks to whoever is debugging. `paused` is called every time the program
+// stops and doesn't return until the user decides how to continue.
+pub trait DebugFrontend {
+    fn paused(&mut self, paused: &mut Paused) -> Resume;
+}
+
+// One function call on the call stack, or the script itself at the bottom.
+pub struct Frame {
+    pub name: String,
+    pub line: u32, // line of the statement running in this frame
+    environment: Rc<RefCell<Environment>>, // innermost environment of that statement
+}
+
+impl Frame {
+    // every environment visible from the frame, innermost first and the globals last
+    pub fn scopes(&self) -> Vec<Vec<(Symbol, LoxValue)>> {
+        let mut scopes = vec![];
+        let mut current = Some(Rc::clone(&self.environment));
+        while let Some(environment) = current {
+            scopes.push(environment.borrow().bindings());
+            current = environment.borrow().enclosing();
+        }
+        scopes
+    }
+
+    // Evaluates `source` as an expression in this frame. Local variables are
+    // found by rebuilding the resolver's scopes from the environment chain,
+    // which mirrors them one to one.
+    fn evaluate(&self, source: &str, runtime: &mut Runtime) -> Result<LoxValue, LoxError> {
+        let mut diagnostics = Diagnostics::with_source(source);
+
+        let tokens = frontend::scan(source, &mut diagnostics);
+        let mut parser = Parser::new(tokens);
+        let expr = parser.parse_expression();
+        diagnostics.append(&mut parser.take_diagnostics());
+
+        let mut scopes = vec![];
+        let mut current = Some(Rc::clone(&self.environment));
+        while let Some(environment) = current {
+            let enclosing = environment.borrow().enclosing();
+            // the outermost environment holds the globals, which the resolver leaves unresolved
+            if enclosing.is_some() {
+                let names: HashMap<Symbol, bool> = environment
+                    .borrow()
+                    .bindings()
+                    .into_iter()
+                    .map(|(name, _)| (name, true))
+                    .collect();
+                scopes.push(names);
+            }
+            current = enclosing;
+        }
+        scopes.reverse();
+
+        let mut resolver = Resolver::with_scopes(scopes);
+        if let Some(expr) = &expr {
+            resolver.resolve_expression(expr);
+        }
+        diagnostics.append(&mut resolver.take_diagnostics());
+
+        let expr = match expr {
+            Some(expr) if !diagnostics.has_errors() => expr,
+            _ => return Err(LoxError::Compile(diagnostics)),
+        };
+        expr.interpret(&self.environment, runtime).map_err(|e| {
+            diagnostics.runtime_error(&e);
+            LoxError::Runtime(diagnostics)
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stepping {
+    Entry,
+    Run,
+    Into,
+    Over(usize), // stop once the call stack is no deeper than this
+    Out(usize),  // stop once the call stack is shallower than this
+}
+
+struct DebugState {
+    file: String,
+    source: String,
+    breakpoints: HashSet<(String, u32)>,
+    frames: Vec<Frame>,
+    stepping: Stepping,
+    last_stop: (usize, u32), // call depth and line of the last statement seen
+}
+
+impl DebugState {
+    fn has_breakpoint(&self, line: u32) -> bool {
+        self.breakpoints
+            .iter()
+            .any(|(file, l)| *l == line && same_file(file, &self.file))
+    }
+}
+
+// Breakpoints may name the script by any trailing part of its path.
+fn same_file(breakpoint: &str, file: &str) -> bool {
+    Path::new(file).ends_with(breakpoint) || Path::new(breakpoint).ends_with(file)
+}
+
+// What a front end can see and change while the program is stopped.
+pub struct Paused<'a> {
+    state: &'a mut DebugState,
+    reason: StopReason,
+    runtime: &'a mut Runtime,
+}
+
+impl Paused<'_> {
+    pub fn reason(&self) -> StopReason {
+        self.reason
+    }
+
+    pub fn file(&self) -> &str {
+        &self.state.file
+    }
+
+    pub fn line(&self) -> u32 {
+        self.top_frame().line
+    }
+
+    pub fn source_line(&self, line: u32) -> Option<&str> {
+        self.state
+            .source
+            .lines()
+            .nth((line as usize).checked_sub(1)?)
+    }
+
+    // the call stack, innermost call first
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.state.frames.iter().rev()
+    }
+
+    pub fn top_frame(&self) -> &Frame {
+        self.state
+            .frames
+            .last()
+            .expect("the script frame is never popped")
+    }
+
+    // Evaluates `source` in the frame `frames` yields at position `frame`, or
+    // returns `None` if there are fewer frames than that.
+    pub fn evaluate(&mut self, frame: usize, source: &str) -> Option<Result<LoxValue, LoxError>> {
+        let frames = &self.state.frames;
+        let frame = &frames[frames.len().checked_sub(frame + 1)?];
+        Some(frame.evaluate(source, self.runtime))
+    }
+
+    pub fn breakpoints(&self) -> Vec<(String, u32)> {
+        let mut breakpoints: Vec<(String, u32)> = self.state.breakpoints.iter().cloned().collect();
+        breakpoints.sort();
+        breakpoints
+    }
+
+    pub fn set_breakpoint(&mut self, file: &str, line: u32) {
+        self.state.breakpoints.insert((String::from(file), line));
+    }
+
+    pub fn clear_breakpoint(&mut self, file: &str, line: u32) -> bool {
+        self.state.breakpoints.remove(&(String::from(file), line))
+    }
+
+    pub fn clear_breakpoints(&mut self, file: &str) {
+        self.state.breakpoints.retain(|(f, _)| f != file);
+    }
+}
+
+// Pauses the tree-walking interpreter at breakpoints and while stepping.
+// Attach one to an `Interpreter` before running a script; it stops before
+// the first statement so breakpoints can be set.
+pub struct Debugger {
+    frontend: Box<dyn DebugFrontend>,
+    state: DebugState,
+    output: Option<OutputSink>,
+}
+
+impl Debugger {
+    pub fn new(frontend: Box<dyn DebugFrontend>) -> Self {
+        Self {
+            frontend,
+            state: DebugState {
+                file: String::from("<script>"),
+                source: String::new(),
+                breakpoints: HashSet::new(),
+                frames: vec![],
+                stepping: Stepping::Entry,
+                last_stop: (0, 0),
+            },
+            output: None,
+        }
+    }
+
+    // sends what the program prints to `output` instead of stdout
+    pub fn redirect_output(&mut self, output: OutputSink) {
+        self.output = Some(output);
+    }
+
+    pub(crate) fn take_output(&mut self) -> Option<OutputSink> {
+        self.output.take()
+    }
+
+    pub(crate) fn restore_output(&mut self, output: Option<OutputSink>) {
+        self.output = output;
+    }
+
+    pub fn clear_breakpoints(&mut self, file: &str) {
+        self.state.breakpoints.retain(|(f, _)| f != file);
+    }
+
+    pub fn set_file(&mut self, file: &str) {
+        self.state.file = String::from(file);
+    }
+
+    pub fn set_breakpoint(&mut self, file: &str, line: u32) {
+        self.state.breakpoints.insert((String::from(file), line));
+    }
+
+    // called by the interpreter as each run starts
+    pub(crate) fn begin(&mut self, source: &str, globals: &Rc<RefCell<Environment>>) {
+        self.state.source = String::from(source);
+        self.state.frames = vec![Frame {
+            name: String::from("<script>"),
+            line: 0,
+            environment: Rc::clone(globals),
+        }];
+        self.state.last_stop = (0, 0);
+    }
+
+    pub(crate) fn statement(
+        &mut self,
+        span: Span,
+        environment: &Rc<RefCell<Environment>>,
+        runtime: &mut Runtime,
+    ) {
+        let depth = self.state.frames.len();
+        if let Some(frame) = self.state.frames.last_mut() {
+            frame.line = span.line;
+            frame.environment = Rc::clone(environment);
+        }
+
+        // statements sharing a line, like a block and its first statement, stop once
+        if self.state.last_stop == (depth, span.line) {
+            return;
+        }
+        self.state.last_stop = (depth, span.line);
+
+        let reason = if self.state.has_breakpoint(span.line) {
+            StopReason::Breakpoint
+        } else {
+            match self.state.stepping {
+                Stepping::Entry => StopReason::Entry,
+                Stepping::Into => StopReason::Step,
+                Stepping::Over(d) if depth <= d => StopReason::Step,
+                Stepping::Out(d) if depth < d => StopReason::Step,
+                _ => return,
+            }
+        };
+
+        let resume = self.frontend.paused(&mut Paused {
+            state: &mut self.state,
+            reason,
+            runtime,
+        });
+        self.state.stepping = match resume {
+            Resume::Continue => Stepping::Run,
+            Resume::StepInto => Stepping::Into,
+            Resume::StepOver => Stepping::Over(depth),
+            Resume::StepOut => Stepping::Out(depth),
+        };
+    }
+
+    pub(crate) fn enter_function(&mut self, name: &str, environment: &Rc<RefCell<Environment>>) {
+        self.state.frames.push(Frame {
+            name: String::from(name),
+            line: 0,
+            environment: Rc::clone(environment),
+        });
+    }
+
+    pub(crate) fn exit_function(&mut self) {
+        self.state.frames.pop();
+    }
+}
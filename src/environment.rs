@@ -1,39 +1,133 @@
-use std::{any::Any, collections::HashMap};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use crate::{errors, token::Token};
-
-#[derive(Clone)]
-pub enum EnvironmentValue {
-    Number(f64),
-    Str(String),
-    Boolean(bool),
-}
-
-pub enum EnvironmentError {
-    UndefinedVariable,
-}
+use crate::{
+    errors::{RuntimeError, RuntimeErrorType},
+    lox_value::LoxValue,
+    symbol::Symbol,
+    token::Token,
+};
 
 pub struct Environment {
-    values: HashMap<String, EnvironmentValue>,
+    values: HashMap<Symbol, LoxValue>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
     pub fn new() -> Self {
         Environment {
             values: HashMap::new(),
+            enclosing: None,
+        }
+    }
+
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
         }
     }
-    pub fn define(&mut self, name: String, value: EnvironmentValue) {
+
+    pub fn define(&mut self, name: Symbol, value: LoxValue) {
         self.values.insert(name, value);
     }
 
-    pub fn get(&self, name: Token) -> Result<EnvironmentValue, EnvironmentError> {
-        if let Some(v) = self.values.get(name.lexeme()) {
+    pub fn assign(&mut self, name: &Token, value: LoxValue) -> Result<(), RuntimeError> {
+        if let Some(v) = self.values.get_mut(name.symbol()) {
+            *v = value;
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(RuntimeError::new(
+                name.span(),
+                String::from(name.lexeme()),
+                RuntimeErrorType::UndefinedVariable,
+            )),
+        }
+    }
+
+    // reads a variable `depth` environments up the chain, or from the globals when
+    // the resolver left it unresolved
+    pub fn get_at(
+        environment: &Rc<RefCell<Environment>>,
+        depth: Option<usize>,
+        name: &Token,
+    ) -> Result<LoxValue, RuntimeError> {
+        Environment::resolve(environment, depth).borrow().get(name)
+    }
+
+    pub fn assign_at(
+        environment: &Rc<RefCell<Environment>>,
+        depth: Option<usize>,
+        name: &Token,
+        value: LoxValue,
+    ) -> Result<(), RuntimeError> {
+        Environment::resolve(environment, depth)
+            .borrow_mut()
+            .assign(name, value)
+    }
+
+    fn resolve(
+        environment: &Rc<RefCell<Environment>>,
+        depth: Option<usize>,
+    ) -> Rc<RefCell<Environment>> {
+        let mut current = Rc::clone(environment);
+        let mut remaining = depth;
+
+        while remaining != Some(0) {
+            let enclosing = match &current.borrow().enclosing {
+                Some(enclosing) => Rc::clone(enclosing),
+                None => break,
+            };
+            current = enclosing;
+            remaining = remaining.map(|d| d - 1);
+        }
+
+        current
+    }
+
+    pub fn enclosing(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.enclosing.clone()
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &LoxValue> {
+        self.values.values()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    // the names defined directly in this environment, sorted by name
+    pub fn bindings(&self) -> Vec<(Symbol, LoxValue)> {
+        let mut bindings: Vec<(Symbol, LoxValue)> = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        bindings.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+        bindings
+    }
+
+    pub fn get(&self, name: &Token) -> Result<LoxValue, RuntimeError> {
+        if let Some(v) = self.values.get(name.symbol()) {
             return Ok(v.clone());
         }
 
-        let error_msg = format!("Undefined variable {}", name.lexeme());
-        errors::error(name.line(), error_msg);
-        return Err(EnvironmentError::UndefinedVariable);
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get(name),
+            None => Err(RuntimeError::new(
+                name.span(),
+                String::from(name.lexeme()),
+                RuntimeErrorType::UndefinedVariable,
+            )),
+        }
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
     }
 }
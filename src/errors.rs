@@ -1,47 +1,313 @@
-use std::{
-    fmt::Display,
-    sync::atomic::{AtomicBool, Ordering},
-};
+use std::{fmt::Display, io, rc::Rc};
 
-static HAD_ERROR: AtomicBool = AtomicBool::new(false);
-static HAD_RUNTIME_ERROR: AtomicBool = AtomicBool::new(false);
+use crate::{linter::Rule, lox_value::LoxValue, token::Span};
 
-pub fn had_error() -> bool {
-    let value = HAD_ERROR.load(Ordering::Relaxed);
-    value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiagnosticKind {
+    Scan,
+    Parse,
+    Resolve,
+    Compile,
+    Runtime,
+    Lint(Rule), // a warning, never stops the program from running
 }
 
-pub fn had_runtime_error() -> bool {
-    let value = HAD_RUNTIME_ERROR.load(Ordering::Relaxed);
-    value
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub span: Span,
+    pub message: String,
+    // the code the span points into when it isn't the source being run, as
+    // for an error raised in a function an earlier run declared
+    pub source: Option<String>,
 }
 
-pub fn error(line: u32, msg: String) {
-    eprintln!("[line {}] Error: {}", line, msg);
-    HAD_ERROR.store(true, Ordering::Relaxed);
+impl Diagnostic {
+    pub fn line(&self) -> u32 {
+        self.span.line
+    }
+
+    pub fn is_warning(&self) -> bool {
+        matches!(self.kind, DiagnosticKind::Lint(_))
+    }
+
+    // the diagnostic followed by the offending source line with its span underlined:
+    //
+    //   [line 1:11] Error: Unexpected token: Expect ';' after value.
+    //      1 | print 1 + ;
+    //        |           ^
+    pub fn render(&self, source: &str) -> String {
+        let Some(before) = source.get(..self.span.offset) else {
+            return self.to_string();
+        };
+
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[self.span.offset..]
+            .find('\n')
+            .map_or(source.len(), |i| self.span.offset + i);
+        let line = source[line_start..line_end].trim_end_matches('\r');
+
+        // keep tabs so the underline stays aligned with the source line
+        let padding: String = source[line_start..self.span.offset]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let end = self.span.end().min(line_end);
+        let width = source[self.span.offset..end].chars().count().max(1);
+
+        let gutter = self.span.line.to_string();
+        format!(
+            "{}\n {} | {}\n {} | {}{}",
+            self,
+            gutter,
+            line,
+            " ".repeat(gutter.len()),
+            padding,
+            "^".repeat(width)
+        )
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self.kind {
+            DiagnosticKind::Runtime => String::from("Runtime error"),
+            DiagnosticKind::Lint(rule) => format!("Warning ({})", rule.name()),
+            _ => String::from("Error"),
+        };
+        write!(
+            f,
+            "[line {}:{}] {}: {}",
+            self.span.line, self.span.column, label, self.message
+        )
+    }
+}
+
+// Collects every error reported during a single run so callers can decide
+// whether to print, inspect, or drop them. When the source is attached the
+// diagnostics are displayed with a snippet of the offending code.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+    source: Option<String>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self {
+            diagnostics: vec![],
+            source: None,
+        }
+    }
+
+    pub fn with_source(source: &str) -> Self {
+        Self {
+            diagnostics: vec![],
+            source: Some(String::from(source)),
+        }
+    }
+
+    pub fn error(&mut self, kind: DiagnosticKind, span: Span, message: String) {
+        self.diagnostics.push(Diagnostic {
+            kind,
+            span,
+            message,
+            source: None,
+        });
+    }
+
+    pub fn warning(&mut self, rule: Rule, span: Span, message: String) {
+        self.error(DiagnosticKind::Lint(rule), span, message);
+    }
+
+    pub fn runtime_error(&mut self, err: &RuntimeError) {
+        let source = err
+            .source()
+            .filter(|source| Some(*source) != self.source.as_deref());
+        self.diagnostics.push(Diagnostic {
+            kind: DiagnosticKind::Runtime,
+            span: err.span(),
+            message: err.message(),
+            source: source.map(String::from),
+        });
+    }
+
+    pub fn append(&mut self, other: &mut Diagnostics) {
+        self.diagnostics.append(&mut other.diagnostics);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| !d.is_warning())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.diagnostics.clear();
+    }
+
+    // puts diagnostics found out of order back in the order of the source
+    pub fn sort(&mut self) {
+        self.diagnostics.sort_by_key(|d| d.span.offset);
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, diagnostic) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            match diagnostic.source.as_ref().or(self.source.as_ref()) {
+                Some(source) => write!(f, "{}", diagnostic.render(source))?,
+                None => write!(f, "{}", diagnostic)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum LoxError {
+    Compile(Diagnostics),
+    Runtime(Diagnostics),
+    Io(io::Error),
 }
 
-pub fn runtime_error(err: &RuntimeError) {
-    eprintln!("{}", err);
-    HAD_RUNTIME_ERROR.store(true, Ordering::Relaxed);
+impl LoxError {
+    pub fn diagnostics(&self) -> Option<&Diagnostics> {
+        match self {
+            Self::Compile(diagnostics) | Self::Runtime(diagnostics) => Some(diagnostics),
+            Self::Io(_) => None,
+        }
+    }
+}
+
+impl Display for LoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Compile(diagnostics) | Self::Runtime(diagnostics) => {
+                write!(f, "{}", diagnostics)
+            }
+            Self::Io(e) => write!(f, "Failed to run file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoxError {}
+
+impl From<io::Error> for LoxError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
 }
 
+#[derive(Debug)]
 pub struct RuntimeError {
-    line: u32,
+    span: Span,
     cause: String,
     error_type: RuntimeErrorType,
+    source: Option<Rc<str>>, // the code `span` points into, once known
 }
 
 impl RuntimeError {
-    pub fn new(line: u32, cause: String, error_type: RuntimeErrorType) -> Self {
+    pub fn new(span: Span, cause: String, error_type: RuntimeErrorType) -> Self {
         Self {
-            line,
+            span,
             cause,
             error_type,
+            source: None,
+        }
+    }
+
+    // Places the error in the code it was raised from. Errors pass through
+    // the functions that called the one raising them, so only the first
+    // source given is kept.
+    pub(crate) fn in_source(mut self, source: &Rc<str>) -> Self {
+        if self.source.is_none() {
+            self.source = Some(Rc::clone(source));
+        }
+        self
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub fn error_type(&self) -> &RuntimeErrorType {
+        &self.error_type
+    }
+
+    pub fn line(&self) -> u32 {
+        self.span.line
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn message(&self) -> String {
+        match self.error_type {
+            RuntimeErrorType::OperandMustBeNumber => {
+                format!("Operand must be a number: {}", self.cause)
+            }
+            RuntimeErrorType::OperandMustBeNumberOrString => format!(
+                "Operands must both be either numbers or strings: {}",
+                self.cause
+            ),
+            RuntimeErrorType::InvalidLiteral => format!("Invalid literal: {}", self.cause),
+            RuntimeErrorType::InvalidUnaryOperator => {
+                format!("Invalid unary operator: {}", self.cause)
+            }
+            RuntimeErrorType::InvalidBinaryOperator => {
+                format!("Invalid binary operator: {}", self.cause)
+            }
+            RuntimeErrorType::DivideByZero => format!("Cannot divide by zero: {}", self.cause),
+            RuntimeErrorType::UndefinedVariable => format!("Undefined variable: {}", self.cause),
+            RuntimeErrorType::NotCallable => {
+                format!("Can only call functions and classes: {}", self.cause)
+            }
+            RuntimeErrorType::ArityMismatch => format!("Wrong number of arguments: {}", self.cause),
+            RuntimeErrorType::UndefinedProperty => format!("Undefined property: {}", self.cause),
+            RuntimeErrorType::OnlyInstancesHaveProperties => {
+                format!("Only instances have properties: {}", self.cause)
+            }
+            RuntimeErrorType::SuperclassMustBeClass => {
+                format!("Superclass must be a class: {}", self.cause)
+            }
+            RuntimeErrorType::StackOverflow => format!("Stack overflow: {}", self.cause),
+            RuntimeErrorType::NotIndexable => {
+                format!("Only lists and maps can be indexed: {}", self.cause)
+            }
+            RuntimeErrorType::InvalidIndex => {
+                format!("Index must be an integer: {}", self.cause)
+            }
+            RuntimeErrorType::IndexOutOfBounds => {
+                format!("Index out of bounds: {}", self.cause)
+            }
+            RuntimeErrorType::UnhashableKey => format!(
+                "Map keys must be numbers, strings, booleans or nil: {}",
+                self.cause
+            ),
+            RuntimeErrorType::UndefinedKey => format!("Undefined key: {}", self.cause),
+            // native functions word their own errors
+            RuntimeErrorType::NativeError => self.cause.clone(),
+            RuntimeErrorType::Return(_) => String::from("Can't return from top-level code"),
         }
     }
 }
 
+#[derive(Debug)]
 pub enum RuntimeErrorType {
     OperandMustBeNumber,
     OperandMustBeNumberOrString,
@@ -49,49 +315,25 @@ pub enum RuntimeErrorType {
     InvalidUnaryOperator,
     InvalidBinaryOperator,
     DivideByZero,
+    UndefinedVariable,
+    NotCallable,
+    ArityMismatch,
+    UndefinedProperty,
+    OnlyInstancesHaveProperties,
+    SuperclassMustBeClass,
+    StackOverflow,
+    NotIndexable,
+    InvalidIndex,
+    IndexOutOfBounds,
+    UnhashableKey,
+    UndefinedKey,
+    NativeError,
+    // not a real error: unwinds the call stack from a `return` statement up to its function call
+    Return(LoxValue),
 }
 
 impl Display for RuntimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.error_type {
-            RuntimeErrorType::OperandMustBeNumber => {
-                write!(
-                    f,
-                    "[line {}]: Operand must be a number: {}",
-                    self.line, self.cause
-                )
-            }
-            RuntimeErrorType::OperandMustBeNumberOrString => {
-                write!(
-                    f,
-                    "[line {}]: Operands must both be either numbers or strings: {}",
-                    self.line, self.cause
-                )
-            }
-            RuntimeErrorType::InvalidLiteral => {
-                write!(f, "[line {}]: Invalid literal: {}", self.line, self.cause)
-            }
-            RuntimeErrorType::InvalidUnaryOperator => {
-                write!(
-                    f,
-                    "[line {}]: Invalid unary operator: {}",
-                    self.line, self.cause
-                )
-            }
-            RuntimeErrorType::InvalidBinaryOperator => {
-                write!(
-                    f,
-                    "[line {}]: Invalid binary operator: {}",
-                    self.line, self.cause
-                )
-            }
-            RuntimeErrorType::DivideByZero => {
-                write!(
-                    f,
-                    "[line {}]: Cannot divide by zero: {}",
-                    self.line, self.cause
-                )
-            }
-        }
+        write!(f, "[line {}]: {}", self.span.line, self.message())
     }
 }
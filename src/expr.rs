@@ -1,17 +1,56 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
 use crate::{
+    environment::Environment,
     errors::{RuntimeError, RuntimeErrorType},
     interpretable::Interpretable,
+    lox_instance::LoxInstance,
+    lox_list::LoxList,
+    lox_map::LoxMap,
     lox_value::LoxValue,
-    token::{Token, TokenType},
+    runtime::Runtime,
+    symbol::Symbol,
+    token::{Span, Token, TokenType},
 };
 
 #[derive(Debug, Clone)]
 pub enum Expr {
+    Assign {
+        name: Token,
+        value: Box<Expr>,
+        depth: Cell<Option<usize>>,
+    },
     Binary {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
     },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        value: Box<Expr>,
+    },
+    Super {
+        keyword: Token,
+        method: Token,
+        depth: Cell<Option<usize>>,
+    },
+    This {
+        keyword: Token,
+        depth: Cell<Option<usize>>,
+    },
     Conditional {
         condition: Box<Expr>,
         consequent: Box<Expr>,
@@ -20,43 +59,221 @@ pub enum Expr {
     Grouping {
         expression: Box<Expr>,
     },
+    // `bracket` is the closing one, where indexing errors are reported
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    SetIndex {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    List {
+        elements: Vec<Expr>,
+        span: Span,
+    },
+    // entries are keys paired with their values, in source order
+    Map {
+        entries: Vec<(Expr, Expr)>,
+        span: Span,
+    },
     Literal {
         value: Token,
     },
+    Logical {
+        left: Box<Expr>,
+        operator: Token,
+        right: Box<Expr>,
+    },
     Unary {
         operator: Token,
         right: Box<Expr>,
     },
+    // `depth` is filled in by the resolver: how many environments up the chain
+    // the variable lives, or None for globals
     Variable {
         token: Token,
+        depth: Cell<Option<usize>>,
     },
 }
 
+impl Expr {
+    // covers the whole expression, derived from the tokens it was parsed from
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Assign { name, value, .. } => name.span().to(value.span()),
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                left.span().to(right.span())
+            }
+            Expr::Call { callee, paren, .. } => callee.span().to(paren.span()),
+            Expr::Get { object, name } => object.span().to(name.span()),
+            Expr::Set { object, value, .. } => object.span().to(value.span()),
+            Expr::Super {
+                keyword, method, ..
+            } => keyword.span().to(method.span()),
+            Expr::This { keyword, .. } => keyword.span(),
+            Expr::Conditional {
+                condition,
+                alternative,
+                ..
+            } => condition.span().to(alternative.span()),
+            Expr::Grouping { expression } => expression.span(),
+            Expr::Index {
+                object, bracket, ..
+            } => object.span().to(bracket.span()),
+            Expr::SetIndex { object, value, .. } => object.span().to(value.span()),
+            Expr::List { span, .. } | Expr::Map { span, .. } => *span,
+            Expr::Literal { value } => value.span(),
+            Expr::Unary { operator, right } => operator.span().to(right.span()),
+            Expr::Variable { token, .. } => token.span(),
+        }
+    }
+}
+
 impl Interpretable for Expr {
-    fn interpret(&self) -> Result<LoxValue, RuntimeError> {
-        evaluate(self)
+    fn interpret(
+        &self,
+        environment: &Rc<RefCell<Environment>>,
+        runtime: &mut Runtime,
+    ) -> Result<LoxValue, RuntimeError> {
+        evaluate(self, environment, runtime)
     }
 }
 
-fn evaluate(expr: &Expr) -> Result<LoxValue, RuntimeError> {
+fn evaluate(
+    expr: &Expr,
+    environment: &Rc<RefCell<Environment>>,
+    runtime: &mut Runtime,
+) -> Result<LoxValue, RuntimeError> {
     match expr {
+        Expr::Assign { name, value, depth } => {
+            let value = value.interpret(environment, runtime)?;
+            Environment::assign_at(environment, depth.get(), name, value.clone())?;
+            Ok(value)
+        }
         Expr::Literal { value } => evaluate_literal(value),
-        Expr::Grouping { expression } => expression.interpret(),
-        Expr::Unary { operator, right } => evaluate_unary(operator, right),
+        Expr::Logical {
+            left,
+            operator,
+            right,
+        } => evaluate_logical(left, right, operator, environment, runtime),
+        Expr::Grouping { expression } => expression.interpret(environment, runtime),
+        Expr::Unary { operator, right } => evaluate_unary(operator, right, environment, runtime),
         Expr::Binary {
             left,
             operator,
             right,
-        } => evaluate_binary(left, right, operator),
+        } => evaluate_binary(left, right, operator, environment, runtime),
+        Expr::Call {
+            callee,
+            paren,
+            arguments,
+        } => evaluate_call(callee, paren, arguments, environment, runtime),
         Expr::Conditional {
             condition,
             consequent,
             alternative,
-        } => evaluate_conditional(condition, consequent, alternative),
-        Expr::Variable { token } => todo!("implement variable evaluations"),
+        } => evaluate_conditional(condition, consequent, alternative, environment, runtime),
+        Expr::Variable { token, depth } => Environment::get_at(environment, depth.get(), token),
+        Expr::Get { object, name } => match object.interpret(environment, runtime)? {
+            LoxValue::Instance(instance) => LoxInstance::get(&instance, name, runtime),
+            LoxValue::List(list) => LoxList::method(&list, name),
+            LoxValue::Map(map) => LoxMap::method(&map, name),
+            value => Err(RuntimeError::new(
+                name.span(),
+                value.to_string(),
+                RuntimeErrorType::OnlyInstancesHaveProperties,
+            )),
+        },
+        Expr::Set {
+            object,
+            name,
+            value,
+        } => match object.interpret(environment, runtime)? {
+            LoxValue::Instance(instance) => {
+                let value = value.interpret(environment, runtime)?;
+                instance.borrow_mut().set(name, value.clone());
+                Ok(value)
+            }
+            value => Err(RuntimeError::new(
+                name.span(),
+                value.to_string(),
+                RuntimeErrorType::OnlyInstancesHaveProperties,
+            )),
+        },
+        Expr::Index {
+            object,
+            bracket,
+            index,
+        } => {
+            let object = object.interpret(environment, runtime)?;
+            let index = index.interpret(environment, runtime)?;
+            match object {
+                LoxValue::List(list) => list.borrow().get(&index, bracket.span()),
+                LoxValue::Map(map) => map.borrow().get(&index, bracket.span()),
+                value => Err(not_indexable(&value, bracket)),
+            }
+        }
+        Expr::SetIndex {
+            object,
+            bracket,
+            index,
+            value,
+        } => {
+            let object = object.interpret(environment, runtime)?;
+            let index = index.interpret(environment, runtime)?;
+            let value = value.interpret(environment, runtime)?;
+            match object {
+                LoxValue::List(list) => {
+                    list.borrow_mut()
+                        .set(&index, value.clone(), bracket.span())?
+                }
+                LoxValue::Map(map) => {
+                    map.borrow_mut()
+                        .set(&index, value.clone(), bracket.span())?
+                }
+                object => return Err(not_indexable(&object, bracket)),
+            }
+            Ok(value)
+        }
+        Expr::List { elements, .. } => {
+            let mut values = vec![];
+            for element in elements {
+                values.push(element.interpret(environment, runtime)?);
+            }
+            Ok(LoxValue::List(
+                runtime.collector().list(LoxList::new(values)),
+            ))
+        }
+        Expr::Map { entries, .. } => {
+            let mut map = LoxMap::new();
+            for (key, value) in entries {
+                let key_value = key.interpret(environment, runtime)?;
+                let value = value.interpret(environment, runtime)?;
+                map.set(&key_value, value, key.span())?;
+            }
+            Ok(LoxValue::Map(runtime.collector().map(map)))
+        }
+        Expr::This { keyword, depth } => Environment::get_at(environment, depth.get(), keyword),
+        Expr::Super {
+            keyword,
+            method,
+            depth,
+        } => evaluate_super(keyword, method, depth.get(), environment, runtime),
     }
 }
 
+fn not_indexable(value: &LoxValue, bracket: &Token) -> RuntimeError {
+    RuntimeError::new(
+        bracket.span(),
+        value.to_string(),
+        RuntimeErrorType::NotIndexable,
+    )
+}
+
 fn evaluate_literal(value: &Token) -> Result<LoxValue, RuntimeError> {
     match value.type_info() {
         TokenType::Number(n) => Ok(LoxValue::Number(*n)),
@@ -66,37 +283,36 @@ fn evaluate_literal(value: &Token) -> Result<LoxValue, RuntimeError> {
         TokenType::Nil => Ok(LoxValue::Nil),
         TokenType::EndOfFile => Ok(LoxValue::Nil),
         _ => Err(RuntimeError::new(
-            value.line(),
+            value.span(),
             String::from(value.lexeme()),
             RuntimeErrorType::InvalidLiteral,
         )),
     }
 }
 
-fn evaluate_unary(operator: &Token, operand: &Box<Expr>) -> Result<LoxValue, RuntimeError> {
-    let right_value = operand.interpret()?;
+fn evaluate_unary(
+    operator: &Token,
+    operand: &Expr,
+    environment: &Rc<RefCell<Environment>>,
+    runtime: &mut Runtime,
+) -> Result<LoxValue, RuntimeError> {
+    let right_value = operand.interpret(environment, runtime)?;
     match operator.type_info() {
         TokenType::Minus => {
             if let LoxValue::Number(n) = right_value {
                 Ok(LoxValue::Number(-n))
             } else {
                 Err(RuntimeError::new(
-                    operator.line(),
+                    operator.span(),
                     String::from(operator.lexeme()),
                     RuntimeErrorType::OperandMustBeNumber,
                 ))
             }
         }
 
-        // handle falsiness (false, nil are both falsey, everything else is truthy)
-        // default case is false because we are doing a logical not here
-        TokenType::Bang => match right_value {
-            LoxValue::Bool(b) => Ok(LoxValue::Bool(!b)),
-            LoxValue::Nil => Ok(LoxValue::Bool(true)),
-            _ => Ok(LoxValue::Bool(false)),
-        },
+        TokenType::Bang => Ok(LoxValue::Bool(!right_value.is_truthy())),
         _ => Err(RuntimeError::new(
-            operator.line(),
+            operator.span(),
             String::from(operator.lexeme()),
             RuntimeErrorType::InvalidUnaryOperator,
         )),
@@ -104,18 +320,20 @@ fn evaluate_unary(operator: &Token, operand: &Box<Expr>) -> Result<LoxValue, Run
 }
 
 fn evaluate_binary(
-    left: &Box<Expr>,
-    right: &Box<Expr>,
+    left: &Expr,
+    right: &Expr,
     operator: &Token,
+    environment: &Rc<RefCell<Environment>>,
+    runtime: &mut Runtime,
 ) -> Result<LoxValue, RuntimeError> {
-    let left_value = left.interpret()?;
-    let right_value = right.interpret()?;
+    let left_value = left.interpret(environment, runtime)?;
+    let right_value = right.interpret(environment, runtime)?;
 
     match operator.type_info() {
         TokenType::Minus => match (left_value, right_value) {
             (LoxValue::Number(n1), LoxValue::Number(n2)) => Ok(LoxValue::Number(n1 - n2)),
             _ => Err(RuntimeError::new(
-                operator.line(),
+                operator.span(),
                 String::from(operator.lexeme()),
                 RuntimeErrorType::OperandMustBeNumber,
             )),
@@ -123,9 +341,11 @@ fn evaluate_binary(
 
         TokenType::Plus => match (left_value, right_value) {
             (LoxValue::Number(n1), LoxValue::Number(n2)) => Ok(LoxValue::Number(n1 + n2)),
-            (LoxValue::Str(s1), LoxValue::Str(s2)) => Ok(LoxValue::Str(format!("{}{}", s1, s2))),
+            (LoxValue::Str(s1), LoxValue::Str(s2)) => {
+                Ok(LoxValue::Str(Symbol::intern(&format!("{}{}", s1, s2))))
+            }
             _ => Err(RuntimeError::new(
-                operator.line(),
+                operator.span(),
                 String::from(operator.lexeme()),
                 RuntimeErrorType::OperandMustBeNumberOrString,
             )),
@@ -135,7 +355,7 @@ fn evaluate_binary(
             (LoxValue::Number(n1), LoxValue::Number(n2)) => {
                 if n2 == 0.0 {
                     Err(RuntimeError::new(
-                        operator.line(),
+                        operator.span(),
                         String::from(operator.lexeme()),
                         RuntimeErrorType::DivideByZero,
                     ))
@@ -144,7 +364,7 @@ fn evaluate_binary(
                 }
             }
             _ => Err(RuntimeError::new(
-                operator.line(),
+                operator.span(),
                 String::from(operator.lexeme()),
                 RuntimeErrorType::OperandMustBeNumber,
             )),
@@ -153,7 +373,7 @@ fn evaluate_binary(
         TokenType::Star => match (left_value, right_value) {
             (LoxValue::Number(n1), LoxValue::Number(n2)) => Ok(LoxValue::Number(n1 * n2)),
             _ => Err(RuntimeError::new(
-                operator.line(),
+                operator.span(),
                 String::from(operator.lexeme()),
                 RuntimeErrorType::OperandMustBeNumber,
             )),
@@ -162,7 +382,7 @@ fn evaluate_binary(
         TokenType::Greater => match (left_value, right_value) {
             (LoxValue::Number(n1), LoxValue::Number(n2)) => Ok(LoxValue::Bool(n1 > n2)),
             _ => Err(RuntimeError::new(
-                operator.line(),
+                operator.span(),
                 String::from(operator.lexeme()),
                 RuntimeErrorType::OperandMustBeNumber,
             )),
@@ -171,7 +391,7 @@ fn evaluate_binary(
         TokenType::GreaterEqual => match (left_value, right_value) {
             (LoxValue::Number(n1), LoxValue::Number(n2)) => Ok(LoxValue::Bool(n1 >= n2)),
             _ => Err(RuntimeError::new(
-                operator.line(),
+                operator.span(),
                 String::from(operator.lexeme()),
                 RuntimeErrorType::OperandMustBeNumber,
             )),
@@ -180,7 +400,7 @@ fn evaluate_binary(
         TokenType::Less => match (left_value, right_value) {
             (LoxValue::Number(n1), LoxValue::Number(n2)) => Ok(LoxValue::Bool(n1 < n2)),
             _ => Err(RuntimeError::new(
-                operator.line(),
+                operator.span(),
                 String::from(operator.lexeme()),
                 RuntimeErrorType::OperandMustBeNumber,
             )),
@@ -189,46 +409,125 @@ fn evaluate_binary(
         TokenType::LessEqual => match (left_value, right_value) {
             (LoxValue::Number(n1), LoxValue::Number(n2)) => Ok(LoxValue::Bool(n1 <= n2)),
             _ => Err(RuntimeError::new(
-                operator.line(),
+                operator.span(),
                 String::from(operator.lexeme()),
                 RuntimeErrorType::OperandMustBeNumber,
             )),
         },
 
-        TokenType::EqualEqual => match (left_value, right_value) {
-            (LoxValue::Number(n1), LoxValue::Number(n2)) => Ok(LoxValue::Bool(n1 == n2)),
-            (LoxValue::Str(s1), LoxValue::Str(s2)) => Ok(LoxValue::Bool(s1 == s2)),
-            (LoxValue::Bool(b1), LoxValue::Bool(b2)) => Ok(LoxValue::Bool(b1 == b2)),
-            (LoxValue::Nil, LoxValue::Nil) => Ok(LoxValue::Bool(true)),
-            _ => Ok(LoxValue::Bool(false)),
-        },
+        // values of different types are never equal, functions are only equal to themselves
+        TokenType::EqualEqual => Ok(LoxValue::Bool(left_value == right_value)),
 
-        TokenType::BangEqual => match (left_value, right_value) {
-            (LoxValue::Number(n1), LoxValue::Number(n2)) => Ok(LoxValue::Bool(n1 != n2)),
-            (LoxValue::Str(s1), LoxValue::Str(s2)) => Ok(LoxValue::Bool(s1 != s2)),
-            (LoxValue::Bool(b1), LoxValue::Bool(b2)) => Ok(LoxValue::Bool(b1 != b2)),
-            _ => Ok(LoxValue::Bool(true)),
-        },
+        TokenType::BangEqual => Ok(LoxValue::Bool(left_value != right_value)),
 
         TokenType::Comma => {
             // don't do much here except ignore the first half after evaulating it
             Ok(right_value)
         }
         _ => Err(RuntimeError::new(
-            operator.line(),
+            operator.span(),
             String::from(operator.lexeme()),
             RuntimeErrorType::InvalidBinaryOperator,
         )),
     }
 }
 
+fn evaluate_call(
+    callee: &Expr,
+    paren: &Token,
+    arguments: &[Expr],
+    environment: &Rc<RefCell<Environment>>,
+    runtime: &mut Runtime,
+) -> Result<LoxValue, RuntimeError> {
+    let callee_value = callee.interpret(environment, runtime)?;
+
+    let mut argument_values = vec![];
+    for argument in arguments {
+        argument_values.push(argument.interpret(environment, runtime)?);
+    }
+
+    let Some(callable) = callee_value.as_callable() else {
+        return Err(RuntimeError::new(
+            paren.span(),
+            callee_value.to_string(),
+            RuntimeErrorType::NotCallable,
+        ));
+    };
+
+    if argument_values.len() != callable.arity() {
+        return Err(RuntimeError::new(
+            paren.span(),
+            format!(
+                "expected {} but got {}",
+                callable.arity(),
+                argument_values.len()
+            ),
+            RuntimeErrorType::ArityMismatch,
+        ));
+    }
+
+    runtime.enter_call(&callee_value, paren.span())?;
+    let result = callable.call(argument_values, paren.span(), runtime);
+    runtime.exit_call();
+    result
+}
+
+fn evaluate_super(
+    keyword: &Token,
+    method: &Token,
+    depth: Option<usize>,
+    environment: &Rc<RefCell<Environment>>,
+    runtime: &mut Runtime,
+) -> Result<LoxValue, RuntimeError> {
+    let LoxValue::Class(superclass) = Environment::get_at(environment, depth, keyword)? else {
+        return Err(RuntimeError::new(
+            keyword.span(),
+            String::from(keyword.lexeme()),
+            RuntimeErrorType::SuperclassMustBeClass,
+        ));
+    };
+
+    // `this` is always bound one environment inside the one holding `super`
+    let this = Token::new(TokenType::This, Symbol::intern("this"), keyword.span());
+    let instance = Environment::get_at(environment, depth.map(|d| d - 1), &this)?;
+
+    match superclass.find_method(method.symbol()) {
+        Some(function) => Ok(LoxValue::Function(function.bind(instance, runtime))),
+        None => Err(RuntimeError::new(
+            method.span(),
+            String::from(method.lexeme()),
+            RuntimeErrorType::UndefinedProperty,
+        )),
+    }
+}
+
 fn evaluate_conditional(
     condition: &Expr,
     consequent: &Expr,
     alternative: &Expr,
+    environment: &Rc<RefCell<Environment>>,
+    runtime: &mut Runtime,
 ) -> Result<LoxValue, RuntimeError> {
-    match condition.interpret()? {
-        LoxValue::Bool(false) | LoxValue::Nil => alternative.interpret(),
-        _ => consequent.interpret(),
+    if condition.interpret(environment, runtime)?.is_truthy() {
+        consequent.interpret(environment, runtime)
+    } else {
+        alternative.interpret(environment, runtime)
+    }
+}
+
+fn evaluate_logical(
+    left: &Expr,
+    right: &Expr,
+    operator: &Token,
+    environment: &Rc<RefCell<Environment>>,
+    runtime: &mut Runtime,
+) -> Result<LoxValue, RuntimeError> {
+    let left_value = left.interpret(environment, runtime)?;
+
+    // short circuit: `or` stops on a truthy left side, `and` on a falsey one
+    match operator.type_info() {
+        TokenType::Or if left_value.is_truthy() => Ok(left_value),
+        TokenType::And if !left_value.is_truthy() => Ok(left_value),
+        _ => right.interpret(environment, runtime),
     }
 }
@@ -0,0 +1,357 @@
+use crate::{
+    errors::{Diagnostics, LoxError},
+    parser::Parser,
+    scanner::Scanner,
+    token::{Span, Token, TokenType},
+};
+
+// Lines are wrapped after commas and operators once they grow past this.
+const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+// Reprints `source` in the canonical layout: one statement per line, four
+// space indentation, braces on the line that opens them and single spaces
+// around operators. Comments stay where they were relative to the code and
+// single blank lines between statements are kept. Source that doesn't parse
+// is left alone and its errors returned instead.
+//
+// The tokens are reprinted rather than the syntax tree, since the parser
+// turns `for` loops into `while` loops and drops comments.
+pub fn format(source: &str) -> Result<String, LoxError> {
+    let mut diagnostics = Diagnostics::with_source(source);
+
+    let mut scanner = Scanner::new(String::from(source));
+    let tokens = scanner.scan_tokens();
+    let comments = scanner.take_comments();
+    diagnostics.append(&mut scanner.take_diagnostics());
+
+    let mut parser = Parser::new(tokens.clone());
+    parser.parse();
+    diagnostics.append(&mut parser.take_diagnostics());
+
+    if diagnostics.has_errors() {
+        return Err(LoxError::Compile(diagnostics));
+    }
+
+    let mut formatter = Formatter::new(source);
+    let mut comments = comments.into_iter().peekable();
+    for token in &tokens {
+        while let Some(comment) = comments.next_if(|c| c.offset < token.span().offset) {
+            formatter.comment(comment);
+        }
+        if *token.type_info() != TokenType::EndOfFile {
+            formatter.token(token);
+        }
+    }
+    for comment in comments {
+        formatter.comment(comment);
+    }
+    Ok(formatter.finish())
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    out: String,
+    line: String,       // the line being built, indentation included
+    breaks: Vec<usize>, // where in `line` it may be wrapped
+    continued: bool,    // the next line carries on a statement
+    indent: usize,
+    parens: usize,               // open parentheses, so `for` clauses stay on one line
+    previous: Option<TokenType>, // the last token written
+    previous_unary: bool,        // whether that token was a prefix operator
+    previous_map_token: bool,    // whether that token was a brace or colon of a map literal
+    previous_line: u32,          // source line the last token or comment ended on
+    last_was_comment: bool,      // a comment was written after `previous`
+    line_ends_in_comment: bool,  // nothing more may be added to this line
+    pending_break: bool,         // the current line is finished unless the next token joins it
+    // open braces, innermost last: None for a block, or for a map literal how
+    // many of the `?` inside it are still waiting for their `:`
+    braces: Vec<Option<usize>>,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            out: String::new(),
+            line: String::new(),
+            breaks: vec![],
+            continued: false,
+            indent: 0,
+            parens: 0,
+            previous: None,
+            previous_unary: false,
+            previous_map_token: false,
+            previous_line: 0,
+            last_was_comment: false,
+            line_ends_in_comment: false,
+            pending_break: false,
+            braces: vec![],
+        }
+    }
+
+    fn token(&mut self, token: &Token) {
+        let kind = token.type_info();
+        // map literals stay on the line like any other expression
+        let map_brace = match kind {
+            TokenType::LeftBrace => self.opens_map(),
+            TokenType::RightBrace => matches!(self.braces.last(), Some(Some(_))),
+            _ => false,
+        };
+        let map_colon = *kind == TokenType::Colon && self.braces.last() == Some(&Some(0));
+        if *kind == TokenType::RightBrace && !map_brace {
+            self.indent = self.indent.saturating_sub(1);
+        }
+
+        // `} else` and empty blocks `{}` stay together
+        let joins = !self.last_was_comment
+            && matches!(
+                (&self.previous, kind),
+                (Some(TokenType::RightBrace), TokenType::Else)
+                    | (Some(TokenType::LeftBrace), TokenType::RightBrace)
+            );
+
+        if self.pending_break && !joins {
+            self.break_line(token.line(), *kind == TokenType::RightBrace);
+        } else if self.line_ends_in_comment {
+            // a line comment inside a statement
+            self.end_line();
+            self.continued = true;
+        }
+        self.pending_break = false;
+
+        let text = token.lexeme();
+        if self.line.is_empty() {
+            self.start_line();
+        } else if self.space_between(kind, map_brace, map_colon) {
+            if self.breakable() {
+                self.breaks.push(self.line.len());
+            }
+            self.line.push(' ');
+        }
+        self.line.push_str(text);
+
+        self.previous_unary = match kind {
+            TokenType::Bang => true,
+            TokenType::Minus => !self.after_operand(),
+            _ => false,
+        };
+        self.previous_map_token = map_brace || map_colon;
+        self.previous = Some(kind.clone());
+        self.previous_line = token.line() + text.matches('\n').count() as u32;
+        self.last_was_comment = false;
+
+        match kind {
+            TokenType::LeftParen => self.parens += 1,
+            TokenType::RightParen => self.parens = self.parens.saturating_sub(1),
+            TokenType::LeftBrace if map_brace => self.braces.push(Some(0)),
+            TokenType::LeftBrace => {
+                self.braces.push(None);
+                self.indent += 1;
+                self.pending_break = true;
+            }
+            TokenType::RightBrace => {
+                self.braces.pop();
+                self.pending_break = !map_brace;
+            }
+            TokenType::QuestionMark => {
+                if let Some(Some(questions)) = self.braces.last_mut() {
+                    *questions += 1;
+                }
+            }
+            TokenType::Colon if !map_colon => {
+                if let Some(Some(questions)) = self.braces.last_mut() {
+                    *questions -= 1;
+                }
+            }
+            TokenType::Semicolon if self.parens == 0 => self.pending_break = true,
+            _ => {}
+        }
+    }
+
+    fn comment(&mut self, span: Span) {
+        let text = self.source[span.offset..span.end()].trim_end();
+        let is_line_comment = text.starts_with("//");
+
+        if self.previous.is_some() && span.line == self.previous_line && !self.line.is_empty() {
+            // trailing the code on its line
+            self.line.push(' ');
+        } else {
+            if self.pending_break {
+                self.break_line(span.line, false);
+            } else if !self.line.is_empty() {
+                self.end_line();
+                self.continued = true;
+            }
+            self.start_line();
+        }
+        self.line.push_str(text);
+
+        self.previous_line = span.line + text.matches('\n').count() as u32;
+        self.last_was_comment = true;
+        self.line_ends_in_comment = is_line_comment;
+        // a comment on its own line always ends it
+        if !self.pending_break && self.line.trim_start() == text {
+            self.pending_break = true;
+        }
+    }
+
+    // Finishes the current line before something starting on `next_line`
+    // of the source, keeping one blank line where the source had any.
+    fn break_line(&mut self, next_line: u32, closing_brace: bool) {
+        self.end_line();
+        let after_open = self.previous == Some(TokenType::LeftBrace) && !self.last_was_comment;
+        if next_line > self.previous_line + 1 && !after_open && !closing_brace {
+            self.out.push('\n');
+        }
+    }
+
+    fn start_line(&mut self) {
+        self.line.push_str(&INDENT.repeat(self.indent));
+        if self.continued {
+            self.line.push_str(INDENT);
+        }
+    }
+
+    // Writes out the current line, wrapped at as few of its break points as
+    // keep it within the width. Wrapped parts are indented one level deeper.
+    fn end_line(&mut self) {
+        if !self.line.is_empty() {
+            let leading = self.line.len() - self.line.trim_start().len();
+            let mut indent = String::from(&self.line[..leading]);
+            if !self.continued {
+                indent.push_str(INDENT);
+            }
+
+            let mut start = 0;
+            let mut width = 0;
+            for end in self.breaks.drain(..).chain([self.line.len()]) {
+                let part = &self.line[start..end];
+                let part_width = part.chars().count();
+                if start > 0 && width + part_width > MAX_WIDTH {
+                    let part = part.trim_start();
+                    self.out.push('\n');
+                    self.out.push_str(&indent);
+                    width = indent.len() + part.chars().count();
+                    self.out.push_str(part);
+                } else {
+                    width += part_width;
+                    self.out.push_str(part);
+                }
+                start = end;
+            }
+
+            let trimmed = self.out.trim_end_matches([' ', '\t']).len();
+            self.out.truncate(trimmed);
+            self.out.push('\n');
+            self.line.clear();
+        }
+        self.continued = false;
+        self.line_ends_in_comment = false;
+    }
+
+    fn finish(mut self) -> String {
+        self.end_line();
+        self.out
+    }
+
+    // `map_brace` and `map_colon` say whether `next` belongs to a map literal
+    fn space_between(&self, next: &TokenType, map_brace: bool, map_colon: bool) -> bool {
+        let Some(previous) = &self.previous else {
+            return false;
+        };
+
+        if self.previous_unary || map_colon || (map_brace && *next == TokenType::RightBrace) {
+            return false;
+        }
+        if self.previous_map_token && *previous == TokenType::LeftBrace {
+            return false;
+        }
+        match (previous, next) {
+            (
+                _,
+                TokenType::Semicolon
+                | TokenType::Comma
+                | TokenType::Dot
+                | TokenType::RightParen
+                | TokenType::RightBracket,
+            ) => false,
+            (TokenType::LeftParen | TokenType::LeftBracket | TokenType::Dot, _) => false,
+            (TokenType::LeftBrace, TokenType::RightBrace) => false,
+            (TokenType::If | TokenType::While | TokenType::For, TokenType::LeftParen) => true,
+            // calls and function declarations
+            (_, TokenType::LeftParen) => !self.after_operand(),
+            // indexing, as opposed to a list literal
+            (_, TokenType::LeftBracket) => !self.after_operand(),
+            _ => true,
+        }
+    }
+
+    // A `{` opens a map literal wherever an expression is expected, which is
+    // anywhere that isn't the start of a statement or the body of a
+    // declaration or control flow statement.
+    fn opens_map(&self) -> bool {
+        match &self.previous {
+            None
+            | Some(
+                TokenType::Semicolon
+                | TokenType::RightBrace
+                | TokenType::RightParen
+                | TokenType::Else
+                | TokenType::Identifier(_),
+            ) => false,
+            Some(TokenType::LeftBrace) => self.previous_map_token,
+            Some(_) => true,
+        }
+    }
+
+    // whether the last token ends an operand, making a following `-` binary
+    // and a following `(` a call or `[` an index
+    fn after_operand(&self) -> bool {
+        if self.previous_map_token && self.previous == Some(TokenType::RightBrace) {
+            return true;
+        }
+        matches!(
+            self.previous,
+            Some(
+                TokenType::Identifier(_)
+                    | TokenType::Str(_)
+                    | TokenType::Number(_)
+                    | TokenType::True
+                    | TokenType::False
+                    | TokenType::Nil
+                    | TokenType::This
+                    | TokenType::Super
+                    | TokenType::RightParen
+                    | TokenType::RightBracket
+            )
+        )
+    }
+
+    // long lines are only wrapped after a comma or binary operator
+    fn breakable(&self) -> bool {
+        if self.previous_unary || self.previous_map_token {
+            return false;
+        }
+        matches!(
+            self.previous,
+            Some(
+                TokenType::Comma
+                    | TokenType::And
+                    | TokenType::Or
+                    | TokenType::Plus
+                    | TokenType::Minus
+                    | TokenType::Star
+                    | TokenType::Slash
+                    | TokenType::EqualEqual
+                    | TokenType::BangEqual
+                    | TokenType::Less
+                    | TokenType::LessEqual
+                    | TokenType::Greater
+                    | TokenType::GreaterEqual
+                    | TokenType::QuestionMark
+                    | TokenType::Colon
+            )
+        )
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{
+    errors::Diagnostics,
+    linter::{self, LintConfig},
+    parser::Parser,
+    resolver::{Definition, Resolver},
+    scanner::Scanner,
+    stmt::Stmt,
+    token::{Span, Token, TokenType},
+};
+
+// Splits `source` into tokens, always ending with an end-of-file token.
+pub fn scan(source: &str, diagnostics: &mut Diagnostics) -> Vec<Token> {
+    scan_with_comments(source, diagnostics).0
+}
+
+// The tokens along with where every comment was, which only tooling needs.
+pub fn scan_with_comments(source: &str, diagnostics: &mut Diagnostics) -> (Vec<Token>, Vec<Span>) {
+    let mut scanner = Scanner::new(String::from(source));
+    let tokens = scanner.scan_tokens();
+    diagnostics.append(&mut scanner.take_diagnostics());
+    (tokens, scanner.take_comments())
+}
+
+// Whether `source` stops partway through something, so that a prompt should
+// read more lines before running it: an unclosed string, comment, brace,
+// bracket or parenthesis, or a trailing operator still waiting for its right
+// operand.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut scanner = Scanner::new(String::from(source));
+    let tokens = scanner.scan_tokens();
+    if scanner.is_unterminated() {
+        return true;
+    }
+
+    let mut depth = 0i32;
+    for token in &tokens {
+        match token.type_info() {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => depth -= 1,
+            _ => {}
+        }
+    }
+
+    let last = tokens
+        .iter()
+        .rev()
+        .map(Token::type_info)
+        .find(|t| **t != TokenType::EndOfFile);
+    let continues = matches!(
+        last,
+        Some(
+            TokenType::Comma
+                | TokenType::Dot
+                | TokenType::Minus
+                | TokenType::Plus
+                | TokenType::Slash
+                | TokenType::Star
+                | TokenType::Bang
+                | TokenType::BangEqual
+                | TokenType::Equal
+                | TokenType::EqualEqual
+                | TokenType::Greater
+                | TokenType::GreaterEqual
+                | TokenType::Less
+                | TokenType::LessEqual
+                | TokenType::QuestionMark
+                | TokenType::Colon
+                | TokenType::And
+                | TokenType::Or
+        )
+    );
+    depth > 0 || continues
+}
+
+// Whether the whole of `source` is one expression, with no statement syntax
+// around it.
+pub fn is_expression(source: &str) -> bool {
+    let mut diagnostics = Diagnostics::new();
+    let tokens = scan(source, &mut diagnostics);
+
+    let mut parser = Parser::new(tokens);
+    let parsed = parser.parse_expression().is_some();
+    parsed && !diagnostics.has_errors() && parser.take_diagnostics().is_empty()
+}
+
+// Scans, parses, resolves and lints `source`, reporting every problem found
+// along the way to `diagnostics`. The statements are only safe to run when no
+// errors were reported; warnings never stop them.
+pub fn parse(source: &str, lint: LintConfig, diagnostics: &mut Diagnostics) -> Vec<Stmt> {
+    analyze_into(source, lint, diagnostics).statements
+}
+
+// Like `parse`, for source that must be one expression and nothing else. It
+// becomes the program's only statement, so running it produces its value.
+pub fn parse_expression(
+    source: &str,
+    lint: LintConfig,
+    diagnostics: &mut Diagnostics,
+) -> Vec<Stmt> {
+    let (tokens, comments) = scan_with_comments(source, diagnostics);
+    let mut parser = Parser::new(tokens);
+    let expression = parser.parse_expression();
+    diagnostics.append(&mut parser.take_diagnostics());
+
+    let statements = expression
+        .into_iter()
+        .map(|expression| Stmt::Expression {
+            span: expression.span(),
+            expression,
+        })
+        .collect();
+    resolve_into(source, statements, comments, lint, diagnostics).statements
+}
+
+// either of the ways above of turning source into statements
+pub type Parse = fn(&str, LintConfig, &mut Diagnostics) -> Vec<Stmt>;
+
+// What editor tooling needs to know about a source file, even a broken one.
+pub struct Analysis {
+    pub statements: Vec<Stmt>,
+    pub definitions: Vec<Definition>,
+    pub comments: Vec<Span>,
+    pub diagnostics: Diagnostics,
+}
+
+pub fn analyze(source: &str, lint: LintConfig) -> Analysis {
+    let mut diagnostics = Diagnostics::with_source(source);
+    let mut analysis = analyze_into(source, lint, &mut diagnostics);
+    analysis.diagnostics = diagnostics;
+    analysis
+}
+
+// reports to the caller's diagnostics, leaving the analysis' own empty
+fn analyze_into(source: &str, lint: LintConfig, diagnostics: &mut Diagnostics) -> Analysis {
+    let (tokens, comments) = scan_with_comments(source, diagnostics);
+
+    let mut parser = Parser::new(tokens);
+    let statements = parser.parse();
+    diagnostics.append(&mut parser.take_diagnostics());
+
+    resolve_into(source, statements, comments, lint, diagnostics)
+}
+
+// Resolves the parsed statements, then looks for warnings once the program
+// is known to be free of errors.
+fn resolve_into(
+    source: &str,
+    statements: Vec<Stmt>,
+    comments: Vec<Span>,
+    lint: LintConfig,
+    diagnostics: &mut Diagnostics,
+) -> Analysis {
+    let mut resolver = Resolver::new();
+    resolver.resolve(&statements);
+    diagnostics.append(&mut resolver.take_diagnostics());
+
+    let analysis = Analysis {
+        statements,
+        definitions: resolver.take_definitions(),
+        comments,
+        diagnostics: Diagnostics::new(),
+    };
+    if !diagnostics.has_errors() {
+        diagnostics.append(&mut linter::check(&analysis, source, lint));
+    }
+    analysis
+}
@@ -1,7 +1,13 @@
-use errors::RuntimeError;
+use std::{cell::RefCell, rc::Rc};
 
-use crate::{errors, lox_value::LoxValue};
+use crate::{
+    environment::Environment, errors::RuntimeError, lox_value::LoxValue, runtime::Runtime,
+};
 
 pub trait Interpretable {
-    fn interpret(&self) -> Result<LoxValue, RuntimeError>;
+    fn interpret(
+        &self,
+        environment: &Rc<RefCell<Environment>>,
+        runtime: &mut Runtime,
+    ) -> Result<LoxValue, RuntimeError>;
 }
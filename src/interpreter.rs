@@ -0,0 +1,168 @@
+use std::{cell::RefCell, fs, path::Path, rc::Rc};
+
+use crate::{
+    bytecode::heap::GcConfig,
+    debugger::{Debugger, OutputSink},
+    environment::Environment,
+    errors::{Diagnostics, LoxError, RuntimeError, RuntimeErrorType},
+    frontend::{self, Parse},
+    interpretable::Interpretable,
+    linter::LintConfig,
+    lox_value::LoxValue,
+    native_function::NativeFunction,
+    prelude,
+    runtime::Runtime,
+    stmt::Stmt,
+    symbol::Symbol,
+};
+
+// An embeddable Lox interpreter. Globals defined by one call to `eval` stay
+// visible to later calls on the same interpreter. It starts out with the
+// functions of the prelude defined. Values are reference counted, and the
+// cycles among them are collected as often as the `GcConfig` asks.
+//
+// Lox calls nest at most 1023 deep, as in the VM, and each one recurses
+// natively. So that they can't overflow the thread's stack, calls also fail
+// with a stack overflow error once they have taken more of it than the stack
+// limit, 1 MiB unless `set_stack_limit` changes it. Without optimizations
+// that is only enough for a few dozen calls, so interpreters on threads with
+// a larger stack should raise it (the binary runs on 256 MiB).
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>, // not collected, as it is always in use
+    runtime: Runtime,
+    lint: LintConfig,
+    warnings: Diagnostics, // found in the source last run
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_gc_config(GcConfig::default())
+    }
+
+    pub fn with_gc_config(config: GcConfig) -> Self {
+        let mut interpreter = Self {
+            globals: Rc::new(RefCell::new(Environment::new())),
+            runtime: Runtime::new(config),
+            lint: LintConfig::default(),
+            warnings: Diagnostics::new(),
+        };
+        prelude::define(&mut interpreter);
+        interpreter
+    }
+
+    // Makes `function` callable from Lox as the global `name`, replacing
+    // anything defined there before. Calls with other than `arity` arguments
+    // are runtime errors, as is any error message the function returns.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(Vec<LoxValue>) -> Result<LoxValue, String> + 'static,
+    ) {
+        let native = NativeFunction::new(name, arity, move |arguments, span, _| {
+            function(arguments)
+                .map_err(|cause| RuntimeError::new(span, cause, RuntimeErrorType::NativeError))
+        });
+        self.globals.borrow_mut().define(
+            Symbol::intern(name),
+            LoxValue::NativeFunction(Rc::new(native)),
+        );
+    }
+
+    // runs `source` and returns the value of its last statement
+    pub fn eval(&mut self, source: &str) -> Result<LoxValue, LoxError> {
+        self.execute(source, frontend::parse)
+    }
+
+    // evaluates `source` as a single expression and returns its value
+    pub fn eval_expression(&mut self, source: &str) -> Result<LoxValue, LoxError> {
+        self.execute(source, frontend::parse_expression)
+    }
+
+    fn execute(&mut self, source: &str, parse: Parse) -> Result<LoxValue, LoxError> {
+        let mut diagnostics = Diagnostics::with_source(source);
+
+        let statements = parse(source, self.lint, &mut diagnostics);
+        if diagnostics.has_errors() {
+            self.warnings = Diagnostics::new();
+            return Err(LoxError::Compile(diagnostics));
+        }
+        self.warnings = diagnostics.clone();
+
+        if let Some(debugger) = self.runtime.debugger() {
+            debugger.begin(source, &self.globals);
+        }
+        self.runtime.replace_source(Rc::from(source));
+        self.runtime.start_stack();
+        let result = Self::interpret(&self.globals, &statements, &mut self.runtime);
+
+        result.map_err(|e| {
+            diagnostics.runtime_error(&e);
+            LoxError::Runtime(diagnostics)
+        })
+    }
+
+    fn interpret(
+        globals: &Rc<RefCell<Environment>>,
+        statements: &[Stmt],
+        runtime: &mut Runtime,
+    ) -> Result<LoxValue, RuntimeError> {
+        let mut value = LoxValue::Nil;
+        for statement in statements {
+            value = statement.interpret(globals, runtime)?;
+        }
+        Ok(value)
+    }
+
+    pub fn run_file<P: AsRef<Path>>(&mut self, path: P) -> Result<LoxValue, LoxError> {
+        let source = fs::read_to_string(&path)?;
+        if let Some(debugger) = self.runtime.debugger() {
+            debugger.set_file(&path.as_ref().to_string_lossy());
+        }
+        self.eval(&source)
+    }
+
+    // how many bytes of native stack calls may take, below the frame `eval`
+    // is called from; it must leave room for the frames above that and some
+    // to spare
+    pub fn set_stack_limit(&mut self, bytes: usize) {
+        self.runtime.set_stack_limit(bytes);
+    }
+
+    // which lint rules later runs are checked against
+    pub fn set_lint_config(&mut self, config: LintConfig) {
+        self.lint = config;
+    }
+
+    // The warnings the linter found in the source last run. They don't stop
+    // it from running, and are part of its diagnostics too if it fails.
+    pub fn warnings(&self) -> &Diagnostics {
+        &self.warnings
+    }
+
+    // every global defined so far, sorted by name
+    pub fn globals(&self) -> Vec<(Symbol, LoxValue)> {
+        self.globals.borrow().bindings()
+    }
+
+    // sends what programs print to `output` instead of stdout, unless an
+    // attached debugger redirects it
+    pub fn redirect_output(&mut self, output: OutputSink) {
+        self.runtime.redirect_output(output);
+    }
+
+    // pauses later runs at the debugger's breakpoints and steps
+    pub fn attach_debugger(&mut self, debugger: Debugger) {
+        self.runtime.attach_debugger(debugger);
+    }
+
+    pub fn detach_debugger(&mut self) -> Option<Debugger> {
+        self.runtime.detach_debugger()
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
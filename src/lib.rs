@@ -0,0 +1,33 @@
+pub mod ast_printable;
+pub mod bytecode;
+pub mod collector;
+pub mod debugger;
+pub mod environment;
+pub mod errors;
+pub mod expr;
+pub mod formatter;
+pub mod frontend;
+pub mod interpretable;
+pub mod interpreter;
+pub mod linter;
+pub mod lox_callable;
+pub mod lox_class;
+pub mod lox_function;
+pub mod lox_instance;
+pub mod lox_list;
+pub mod lox_map;
+pub mod lox_value;
+pub mod native_function;
+pub mod parser;
+pub mod prelude;
+pub mod resolver;
+pub mod runtime;
+pub mod scanner;
+pub mod stmt;
+pub mod symbol;
+pub mod token;
+
+pub use bytecode::Vm;
+pub use errors::LoxError;
+pub use interpreter::Interpreter;
+pub use lox_value::LoxValue;
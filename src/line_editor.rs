@@ -0,0 +1,404 @@
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, BufRead, IsTerminal, Read, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+// History kept between sessions, oldest entries dropped first.
+const HISTORY_FILE: &str = ".lox_history";
+const MAX_HISTORY: usize = 1000;
+
+pub enum Input {
+    Line(String),
+    // Ctrl-C, the entry being typed is dropped
+    Interrupted,
+    Eof,
+}
+
+// Reads prompt lines with cursor movement and history when stdin is a
+// terminal, and plain lines otherwise so input can still be piped in.
+pub struct LineEditor {
+    terminal: bool,
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+    completions: Vec<String>, // words Tab can complete, sorted
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        let terminal = io::stdin().is_terminal() && io::stdout().is_terminal();
+        let history_path = env::var_os("HOME")
+            .filter(|_| terminal)
+            .map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        let mut editor = Self {
+            terminal,
+            history: vec![],
+            history_path,
+            completions: vec![],
+        };
+        editor.load_history();
+        editor
+    }
+
+    pub fn read_line(&mut self, prompt: &str) -> Input {
+        print!("{}", prompt);
+        let _ = io::stdout().flush();
+
+        if self.terminal {
+            if let Some(raw) = RawMode::enable() {
+                let input = self.edit(prompt);
+                drop(raw);
+                return input;
+            }
+        }
+
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => Input::Eof,
+            Ok(_) => {
+                let trimmed = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(trimmed);
+                Input::Line(line)
+            }
+        }
+    }
+
+    // the words Tab offers from now on
+    pub fn set_completions(&mut self, mut words: Vec<String>) {
+        words.sort();
+        words.dedup();
+        self.completions = words;
+    }
+
+    // remembers a line for the arrow keys, in this session and the next
+    pub fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(String::from(line));
+
+        if let Some(path) = &self.history_path {
+            let file = OpenOptions::new().create(true).append(true).open(path);
+            if let Ok(mut file) = file {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    fn load_history(&mut self) {
+        let Some(path) = &self.history_path else {
+            return;
+        };
+        let Ok(contents) = fs::read_to_string(path) else {
+            return;
+        };
+
+        self.history = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(String::from)
+            .collect();
+        if self.history.len() > MAX_HISTORY {
+            self.history.drain(..self.history.len() - MAX_HISTORY);
+            let mut contents = self.history.join("\n");
+            contents.push('\n');
+            let _ = fs::write(path, contents);
+        }
+    }
+
+    // Completes the word before the cursor as far as every matching word
+    // agrees, and lists the matches when that doesn't add anything.
+    fn complete(&self, line: &mut Line) {
+        let mut start = line.cursor;
+        while start > 0 && is_word_char(line.chars[start - 1]) {
+            start -= 1;
+        }
+        // commands are only typed at the start of the line
+        if start == 1 && line.chars[0] == ':' {
+            start = 0;
+        }
+        let prefix: String = line.chars[start..line.cursor].iter().collect();
+        if prefix.is_empty() {
+            return;
+        }
+
+        let matches: Vec<&String> = self
+            .completions
+            .iter()
+            .filter(|word| word.starts_with(&prefix))
+            .collect();
+        let Some(first) = matches.first() else {
+            return;
+        };
+        // in bytes, always on a character boundary of `first`
+        let common = matches.iter().fold(first.len(), |common, word| {
+            first
+                .char_indices()
+                .zip(word.chars())
+                .take_while(|((i, a), b)| *i < common && a == b)
+                .last()
+                .map_or(0, |((i, a), _)| i + a.len_utf8())
+        });
+
+        if common > prefix.len() {
+            for c in first[prefix.len()..common].chars() {
+                line.chars.insert(line.cursor, c);
+                line.cursor += 1;
+            }
+        } else if matches.len() > 1 {
+            let words: Vec<&str> = matches.iter().map(|word| word.as_str()).collect();
+            println!("\n{}", words.join("  "));
+        }
+    }
+
+    fn edit(&mut self, prompt: &str) -> Input {
+        let mut line = Line {
+            prompt,
+            chars: vec![],
+            cursor: 0,
+        };
+        // where in the history the arrow keys have got to, and what was being
+        // typed before they were first pressed
+        let mut recalled = self.history.len();
+        let mut draft = vec![];
+
+        loop {
+            let Some(key) = read_key() else {
+                return Input::Eof;
+            };
+            match key {
+                Key::Enter => {
+                    println!();
+                    return Input::Line(line.chars.iter().collect());
+                }
+                Key::Interrupt => {
+                    println!("^C");
+                    return Input::Interrupted;
+                }
+                Key::EndOfInput if line.chars.is_empty() => {
+                    println!();
+                    return Input::Eof;
+                }
+                Key::EndOfInput | Key::Delete => {
+                    if line.cursor < line.chars.len() {
+                        line.chars.remove(line.cursor);
+                    }
+                }
+                Key::Backspace => {
+                    if line.cursor > 0 {
+                        line.cursor -= 1;
+                        line.chars.remove(line.cursor);
+                    }
+                }
+                Key::Char(c) => {
+                    line.chars.insert(line.cursor, c);
+                    line.cursor += 1;
+                }
+                Key::Left => line.cursor = line.cursor.saturating_sub(1),
+                Key::Right => line.cursor = (line.cursor + 1).min(line.chars.len()),
+                Key::Home => line.cursor = 0,
+                Key::End => line.cursor = line.chars.len(),
+                Key::KillToStart => {
+                    line.chars.drain(..line.cursor);
+                    line.cursor = 0;
+                }
+                Key::KillToEnd => line.chars.truncate(line.cursor),
+                Key::KillWord => {
+                    let mut start = line.cursor;
+                    while start > 0 && line.chars[start - 1] == ' ' {
+                        start -= 1;
+                    }
+                    while start > 0 && line.chars[start - 1] != ' ' {
+                        start -= 1;
+                    }
+                    line.chars.drain(start..line.cursor);
+                    line.cursor = start;
+                }
+                Key::Up if recalled > 0 => {
+                    if recalled == self.history.len() {
+                        draft = line.chars.clone();
+                    }
+                    recalled -= 1;
+                    line.replace(self.history[recalled].chars().collect());
+                }
+                Key::Down if recalled < self.history.len() => {
+                    recalled += 1;
+                    match self.history.get(recalled) {
+                        Some(entry) => line.replace(entry.chars().collect()),
+                        None => line.replace(draft.clone()),
+                    }
+                }
+                Key::Tab => self.complete(&mut line),
+                Key::Up | Key::Down | Key::Ignored => continue,
+            }
+            line.render();
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// the line being edited, kept in characters so the cursor moves over whole ones
+struct Line<'a> {
+    prompt: &'a str,
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Line<'_> {
+    fn replace(&mut self, chars: Vec<char>) {
+        self.cursor = chars.len();
+        self.chars = chars;
+    }
+
+    // redraws the whole line, then moves the cursor back into place
+    fn render(&self) {
+        let text: String = self.chars.iter().collect();
+        let mut output = format!("\r{}{}\x1b[K", self.prompt, text);
+        let behind = self.chars.len() - self.cursor;
+        if behind > 0 {
+            output.push_str(&format!("\x1b[{}D", behind));
+        }
+        print!("{}", output);
+        let _ = io::stdout().flush();
+    }
+}
+
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    KillToStart,
+    KillToEnd,
+    KillWord,
+    Tab,
+    Interrupt,
+    EndOfInput,
+    Ignored,
+}
+
+fn read_byte() -> Option<u8> {
+    let mut byte = [0];
+    match io::stdin().lock().read(&mut byte) {
+        Ok(1) => Some(byte[0]),
+        _ => None,
+    }
+}
+
+fn read_key() -> Option<Key> {
+    let key = match read_byte()? {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x02 => Key::Left,
+        0x03 => Key::Interrupt,
+        0x04 => Key::EndOfInput,
+        0x05 => Key::End,
+        0x06 => Key::Right,
+        b'\t' => Key::Tab,
+        0x0b => Key::KillToEnd,
+        0x0e => Key::Down,
+        0x10 => Key::Up,
+        0x15 => Key::KillToStart,
+        0x17 => Key::KillWord,
+        0x1b => read_escape()?,
+        byte if byte < 0x20 => Key::Ignored,
+        byte if byte < 0x80 => Key::Char(byte as char),
+        byte => {
+            // the rest of a UTF-8 encoded character
+            let length = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..length {
+                bytes.push(read_byte()?);
+            }
+            match std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|s| s.chars().next())
+            {
+                Some(c) => Key::Char(c),
+                None => Key::Ignored,
+            }
+        }
+    };
+    Some(key)
+}
+
+// the keys terminals send as `ESC [` or `ESC O` sequences
+fn read_escape() -> Option<Key> {
+    if !matches!(read_byte()?, b'[' | b'O') {
+        return Some(Key::Ignored);
+    }
+
+    let mut parameter = String::new();
+    loop {
+        let byte = read_byte()?;
+        if !(0x40..=0x7e).contains(&byte) {
+            parameter.push(byte as char);
+            continue;
+        }
+        let key = match (byte, parameter.as_str()) {
+            (b'A', _) => Key::Up,
+            (b'B', _) => Key::Down,
+            (b'C', _) => Key::Right,
+            (b'D', _) => Key::Left,
+            (b'H', _) | (b'~', "1" | "7") => Key::Home,
+            (b'F', _) | (b'~', "4" | "8") => Key::End,
+            (b'~', "3") => Key::Delete,
+            _ => Key::Ignored,
+        };
+        return Some(key);
+    }
+}
+
+// Switches the terminal to reading key by key without echo or signals for
+// as long as it is held, so Ctrl-C arrives as a key instead of ending the
+// process. Output processing stays on so `\n` still starts a new line.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> Option<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&[
+            "-icanon", "-echo", "-isig", "-ixon", "-iexten", "min", "1", "time", "0",
+        ])?;
+        Some(Self {
+            saved: String::from(saved.trim()),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+// stty works on the terminal it is given as stdin
+fn stty(arguments: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(arguments)
+        .stdin(Stdio::inherit())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
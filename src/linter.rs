@@ -0,0 +1,475 @@
+use crate::{
+    errors::Diagnostics,
+    expr::Expr,
+    frontend::{self, Analysis},
+    stmt::{FunctionDeclaration, Stmt},
+    symbol::Symbol,
+    token::{Span, Token, TokenType},
+};
+
+// Comments starting with this turn warnings off for their line, or for the
+// next line when the comment is on a line of its own:
+//
+//   // lox-allow: unused-variable, shadowing
+//   var x = 1; // lox-allow
+const SUPPRESSION: &str = "lox-allow";
+
+// The kinds of likely mistakes the linter looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    UnusedVariable,
+    UnusedParameter,
+    UnreachableCode,
+    Shadowing,
+    ConstantComparison,
+    SelfAssignment,
+    EmptyBlock,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 7] = [
+        Rule::UnusedVariable,
+        Rule::UnusedParameter,
+        Rule::UnreachableCode,
+        Rule::Shadowing,
+        Rule::ConstantComparison,
+        Rule::SelfAssignment,
+        Rule::EmptyBlock,
+    ];
+
+    // how the rule is spelled on the command line and in suppression comments
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::UnusedParameter => "unused-parameter",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::Shadowing => "shadowing",
+            Rule::ConstantComparison => "constant-comparison",
+            Rule::SelfAssignment => "self-assignment",
+            Rule::EmptyBlock => "empty-block",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+
+    fn bit(self) -> u8 {
+        1 << Rule::ALL
+            .iter()
+            .position(|r| *r == self)
+            .unwrap_or_default()
+    }
+}
+
+// Which rules are checked. Every rule is on unless turned off.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LintConfig {
+    disabled: u8, // one bit per rule
+}
+
+impl LintConfig {
+    pub fn enable(&mut self, rule: Rule) {
+        self.disabled &= !rule.bit();
+    }
+
+    pub fn disable(&mut self, rule: Rule) {
+        self.disabled |= rule.bit();
+    }
+
+    pub fn disable_all(&mut self) {
+        self.disabled = u8::MAX;
+    }
+
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        self.disabled & rule.bit() == 0
+    }
+}
+
+// Analyzes `source` and lints it. Warnings are only looked for once the
+// program is free of errors, otherwise the errors are returned alone.
+pub fn lint(source: &str, config: LintConfig) -> Diagnostics {
+    frontend::analyze(source, config).diagnostics
+}
+
+// The warnings for a program that has already been resolved, which the front
+// end adds to its diagnostics itself.
+pub fn check(analysis: &Analysis, source: &str, config: LintConfig) -> Diagnostics {
+    let mut linter = Linter {
+        config,
+        suppressions: suppressions(source, &analysis.comments),
+        scopes: vec![vec![]], // the globals
+        diagnostics: Diagnostics::with_source(source),
+    };
+    linter.statements(&analysis.statements);
+    // unused names are only known once their scope ends
+    linter.diagnostics.sort();
+    linter.diagnostics
+}
+
+// the lines each suppression comment covers, and the rule it names or None for all
+fn suppressions(source: &str, comments: &[Span]) -> Vec<(u32, Option<Rule>)> {
+    let mut suppressions = vec![];
+
+    for comment in comments {
+        let text = &source[comment.offset..comment.end()];
+        let text = text
+            .strip_prefix("//")
+            .or_else(|| text.strip_prefix("/*").map(|t| t.trim_end_matches("*/")))
+            .unwrap_or(text)
+            .trim();
+        let Some(rest) = text.strip_prefix(SUPPRESSION) else {
+            continue;
+        };
+
+        let rules: Vec<Option<Rule>> = match rest.trim().strip_prefix(':') {
+            Some(names) => names
+                .split(',')
+                .filter_map(|name| Rule::from_name(name.trim()))
+                .map(Some)
+                .collect(),
+            None if rest.trim().is_empty() => vec![None],
+            None => continue,
+        };
+
+        let line_start = source[..comment.offset].rfind('\n').map_or(0, |i| i + 1);
+        let own_line = source[line_start..comment.offset].trim().is_empty();
+        let last_line = comment.line + text.matches('\n').count() as u32;
+        for rule in rules {
+            suppressions.push((comment.line, rule));
+            if own_line {
+                suppressions.push((last_line + 1, rule));
+            }
+        }
+    }
+    suppressions
+}
+
+// A variable declared in a scope.
+struct Local {
+    name: Symbol,
+    span: Span,
+    rule: Option<Rule>, // what to warn about when it is never read
+    used: bool,
+}
+
+// Walks the syntax tree with its own scopes, like the resolver, keeping
+// track of which locals are read. Unlike the resolver's, its scopes start
+// with the globals, which locals can shadow too.
+struct Linter {
+    config: LintConfig,
+    suppressions: Vec<(u32, Option<Rule>)>,
+    scopes: Vec<Vec<Local>>,
+    diagnostics: Diagnostics,
+}
+
+impl Linter {
+    fn statements(&mut self, statements: &[Stmt]) {
+        let mut returned = false;
+        for statement in statements {
+            if returned {
+                self.warn(
+                    Rule::UnreachableCode,
+                    statement.span(),
+                    String::from("Unreachable code after 'return'."),
+                );
+                // once per block is enough
+                returned = false;
+            }
+            self.statement(statement);
+            if matches!(statement, Stmt::Return { .. }) {
+                returned = true;
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Block { statements, span } => {
+                if statements.is_empty() {
+                    self.warn(Rule::EmptyBlock, *span, String::from("Empty block."));
+                }
+                self.scopes.push(vec![]);
+                self.statements(statements);
+                self.end_scope();
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => {
+                self.declare(name, None);
+                if let Some(superclass) = superclass {
+                    self.expr(superclass);
+                }
+                for method in methods {
+                    self.function(method);
+                }
+            }
+            Stmt::Expression { expression, .. } | Stmt::Print { expression, .. } => {
+                self.expr(expression)
+            }
+            Stmt::Function(declaration) => {
+                self.declare(&declaration.name, None);
+                self.function(declaration);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                if let Some(initializer) = initializer {
+                    self.expr(initializer);
+                }
+                self.declare(name, Some(Rule::UnusedVariable));
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                self.expr(condition);
+                self.statement(body);
+            }
+        }
+    }
+
+    fn function(&mut self, declaration: &FunctionDeclaration) {
+        self.scopes.push(vec![]);
+        for param in &declaration.params {
+            self.declare(param, Some(Rule::UnusedParameter));
+        }
+        self.statements(&declaration.body);
+        self.end_scope();
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                if let Expr::Variable { token, .. } = value.as_ref() {
+                    if token.symbol() == name.symbol() {
+                        self.warn(
+                            Rule::SelfAssignment,
+                            expr.span(),
+                            format!("'{}' is assigned to itself.", name.lexeme()),
+                        );
+                    }
+                }
+                self.expr(value);
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                if let Expr::Get {
+                    object: value_object,
+                    name: value_name,
+                } = value.as_ref()
+                {
+                    if value_name.symbol() == name.symbol() && same_object(object, value_object) {
+                        self.warn(
+                            Rule::SelfAssignment,
+                            expr.span(),
+                            format!("'{}' is assigned to itself.", name.lexeme()),
+                        );
+                    }
+                }
+                self.expr(object);
+                self.expr(value);
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                if let Some(result) = constant_comparison(left, operator, right) {
+                    self.warn(
+                        Rule::ConstantComparison,
+                        expr.span(),
+                        format!("Comparison is always {}.", result),
+                    );
+                }
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Logical { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expr(callee);
+                for argument in arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::Get { object, .. } => self.expr(object),
+            Expr::Index { object, index, .. } => {
+                self.expr(object);
+                self.expr(index);
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expr(object);
+                self.expr(index);
+                self.expr(value);
+            }
+            Expr::List { elements, .. } => {
+                for element in elements {
+                    self.expr(element);
+                }
+            }
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            Expr::Conditional {
+                condition,
+                consequent,
+                alternative,
+            } => {
+                self.expr(condition);
+                self.expr(consequent);
+                self.expr(alternative);
+            }
+            Expr::Grouping { expression } => self.expr(expression),
+            Expr::Unary { right, .. } => self.expr(right),
+            Expr::Variable { token, .. } => self.use_variable(token),
+            Expr::Super { .. } | Expr::This { .. } | Expr::Literal { .. } => {}
+        }
+    }
+
+    // Globals are never unused, as any script loaded later may use them.
+    fn declare(&mut self, name: &Token, rule: Option<Rule>) {
+        let rule = rule.filter(|_| self.scopes.len() > 1);
+        let outer = self.scopes[..self.scopes.len() - 1]
+            .iter()
+            .rev()
+            .flatten()
+            .find(|local| local.name == *name.symbol())
+            .map(|local| local.span.line);
+        if let Some(line) = outer {
+            self.warn(
+                Rule::Shadowing,
+                name.span(),
+                format!(
+                    "'{}' shadows a variable declared on line {}.",
+                    name.lexeme(),
+                    line
+                ),
+            );
+        }
+
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Local {
+                name: name.symbol().clone(),
+                span: name.span(),
+                rule,
+                used: rule.is_none(),
+            });
+        }
+    }
+
+    fn use_variable(&mut self, name: &Token) {
+        let local = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|local| local.name == *name.symbol());
+        if let Some(local) = local {
+            local.used = true;
+        }
+    }
+
+    fn end_scope(&mut self) {
+        for local in self.scopes.pop().unwrap_or_default() {
+            let Some(rule) = local.rule.filter(|_| !local.used) else {
+                continue;
+            };
+            let what = match rule {
+                Rule::UnusedParameter => "parameter",
+                _ => "variable",
+            };
+            self.warn(
+                rule,
+                local.span,
+                format!("Unused {} '{}'.", what, local.name),
+            );
+        }
+    }
+
+    fn warn(&mut self, rule: Rule, span: Span, message: String) {
+        let suppressed = self
+            .suppressions
+            .iter()
+            .any(|(line, r)| *line == span.line && r.is_none_or(|r| r == rule));
+        if self.config.is_enabled(rule) && !suppressed {
+            self.diagnostics.warning(rule, span, message);
+        }
+    }
+}
+
+// `a.x = a.x` and `this.x = this.x`
+fn same_object(left: &Expr, right: &Expr) -> bool {
+    match (left, right) {
+        (Expr::Variable { token: a, .. }, Expr::Variable { token: b, .. }) => {
+            a.symbol() == b.symbol()
+        }
+        (Expr::This { .. }, Expr::This { .. }) => true,
+        _ => false,
+    }
+}
+
+// The outcome of comparing two literals, when it can be known without
+// running the program.
+fn constant_comparison(left: &Expr, operator: &Token, right: &Expr) -> Option<bool> {
+    let (left, right) = (literal(left)?, literal(right)?);
+
+    match (operator.type_info(), left, right) {
+        (TokenType::EqualEqual, left, right) => Some(left == right),
+        (TokenType::BangEqual, left, right) => Some(left != right),
+        (TokenType::Less, TokenType::Number(a), TokenType::Number(b)) => Some(a < b),
+        (TokenType::LessEqual, TokenType::Number(a), TokenType::Number(b)) => Some(a <= b),
+        (TokenType::Greater, TokenType::Number(a), TokenType::Number(b)) => Some(a > b),
+        (TokenType::GreaterEqual, TokenType::Number(a), TokenType::Number(b)) => Some(a >= b),
+        _ => None,
+    }
+}
+
+fn literal(expr: &Expr) -> Option<&TokenType> {
+    match expr {
+        Expr::Literal { value } => match value.type_info() {
+            kind @ (TokenType::Number(_)
+            | TokenType::Str(_)
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil) => Some(kind),
+            _ => None,
+        },
+        Expr::Grouping { expression } => literal(expression),
+        _ => None,
+    }
+}
@@ -0,0 +1,15 @@
+use std::rc::Rc;
+
+use crate::{errors::RuntimeError, lox_value::LoxValue, runtime::Runtime, token::Span};
+
+pub trait LoxCallable {
+    fn arity(&self) -> usize;
+    // `span` is the call's closing parenthesis, where errors raised by the
+    // callee itself are reported
+    fn call(
+        self: Rc<Self>,
+        arguments: Vec<LoxValue>,
+        span: Span,
+        runtime: &mut Runtime,
+    ) -> Result<LoxValue, RuntimeError>;
+}
@@ -0,0 +1,91 @@
+use std::{collections::HashMap, fmt::Debug, rc::Rc};
+
+use crate::{
+    errors::RuntimeError, lox_callable::LoxCallable, lox_function::LoxFunction,
+    lox_instance::LoxInstance, lox_value::LoxValue, runtime::Runtime, symbol::Symbol, token::Span,
+};
+
+pub struct LoxClass {
+    name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<Symbol, Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: String,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<Symbol, Rc<LoxFunction>>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn superclass(&self) -> Option<&Rc<LoxClass>> {
+        self.superclass.as_ref()
+    }
+
+    pub(crate) fn methods(&self) -> impl Iterator<Item = &Rc<LoxFunction>> {
+        self.methods.values()
+    }
+
+    // methods are looked up on the class first and then up the inheritance chain
+    pub fn find_method(&self, name: &Symbol) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self
+                .superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name)),
+        }
+    }
+}
+
+impl LoxCallable for LoxClass {
+    fn arity(&self) -> usize {
+        match self.find_method(&Symbol::intern("init")) {
+            Some(initializer) => initializer.arity(),
+            None => 0,
+        }
+    }
+
+    fn call(
+        self: Rc<Self>,
+        arguments: Vec<LoxValue>,
+        span: Span,
+        runtime: &mut Runtime,
+    ) -> Result<LoxValue, RuntimeError> {
+        let instance = runtime
+            .collector()
+            .instance(LoxInstance::new(Rc::clone(&self)));
+        let instance = LoxValue::Instance(instance);
+
+        if let Some(initializer) = self.find_method(&Symbol::intern("init")) {
+            initializer
+                .bind(instance.clone(), runtime)
+                .call(arguments, span, runtime)?;
+        }
+
+        Ok(instance)
+    }
+}
+
+// classes only compare equal to themselves
+impl PartialEq for LoxClass {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Debug for LoxClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
@@ -0,0 +1,137 @@
+use std::{cell::RefCell, fmt::Debug, rc::Rc};
+
+use crate::{
+    environment::Environment,
+    errors::{RuntimeError, RuntimeErrorType},
+    interpretable::Interpretable,
+    lox_callable::LoxCallable,
+    lox_value::LoxValue,
+    runtime::Runtime,
+    stmt::FunctionDeclaration,
+    symbol::Symbol,
+    token::{Span, Token, TokenType},
+};
+
+pub struct LoxFunction {
+    declaration: Rc<FunctionDeclaration>,
+    closure: Rc<RefCell<Environment>>, // environment the function was declared in
+    is_initializer: bool,              // class `init` methods always return `this`
+    source: Rc<str>,                   // the code it was declared in, which its spans point into
+}
+
+impl LoxFunction {
+    pub fn new(
+        declaration: Rc<FunctionDeclaration>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+        source: Rc<str>,
+    ) -> Self {
+        Self {
+            declaration,
+            closure,
+            is_initializer,
+            source,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.declaration.name.lexeme()
+    }
+
+    pub(crate) fn closure(&self) -> &Rc<RefCell<Environment>> {
+        &self.closure
+    }
+
+    // wraps the closure in a new environment where `this` refers to the given instance
+    pub fn bind(&self, instance: LoxValue, runtime: &mut Runtime) -> Rc<LoxFunction> {
+        let environment = runtime
+            .collector()
+            .environment(Environment::with_enclosing(Rc::clone(&self.closure)));
+        environment
+            .borrow_mut()
+            .define(Symbol::intern("this"), instance);
+
+        runtime.collector().function(LoxFunction::new(
+            Rc::clone(&self.declaration),
+            environment,
+            self.is_initializer,
+            Rc::clone(&self.source),
+        ))
+    }
+
+    fn bound_this(&self) -> Result<LoxValue, RuntimeError> {
+        let this = Token::new(
+            TokenType::This,
+            Symbol::intern("this"),
+            self.declaration.name.span(),
+        );
+        self.closure.borrow().get(&this)
+    }
+}
+
+impl LoxCallable for LoxFunction {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(
+        self: Rc<Self>,
+        arguments: Vec<LoxValue>,
+        _: Span,
+        runtime: &mut Runtime,
+    ) -> Result<LoxValue, RuntimeError> {
+        let environment = runtime
+            .collector()
+            .environment(Environment::with_enclosing(Rc::clone(&self.closure)));
+
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            environment
+                .borrow_mut()
+                .define(param.symbol().clone(), argument);
+        }
+
+        let caller_source = runtime.replace_source(Rc::clone(&self.source));
+        runtime.enter_function(self.name(), &environment);
+        let result = self.run_body(&environment, runtime);
+        runtime.exit_function();
+        runtime.replace_source(caller_source);
+        result.map_err(|e| e.in_source(&self.source))
+    }
+}
+
+impl LoxFunction {
+    fn run_body(
+        &self,
+        environment: &Rc<RefCell<Environment>>,
+        runtime: &mut Runtime,
+    ) -> Result<LoxValue, RuntimeError> {
+        for statement in &self.declaration.body {
+            if let Err(e) = statement.interpret(environment, runtime) {
+                // a `return` unwinds to here as an error carrying the returned value
+                return match e.error_type() {
+                    RuntimeErrorType::Return(_) if self.is_initializer => self.bound_this(),
+                    RuntimeErrorType::Return(value) => Ok(value.clone()),
+                    _ => Err(e),
+                };
+            }
+        }
+
+        if self.is_initializer {
+            return self.bound_this();
+        }
+        Ok(LoxValue::Nil)
+    }
+}
+
+// functions only compare equal to themselves
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Debug for LoxFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
@@ -0,0 +1,76 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Debug, rc::Rc};
+
+use crate::{
+    errors::{RuntimeError, RuntimeErrorType},
+    lox_class::LoxClass,
+    lox_value::LoxValue,
+    runtime::Runtime,
+    symbol::Symbol,
+    token::Token,
+};
+
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: HashMap<Symbol, LoxValue>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Self {
+        Self {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    // takes the shared instance rather than `&self` so methods can be bound to it as `this`
+    pub fn get(
+        instance: &Rc<RefCell<LoxInstance>>,
+        name: &Token,
+        runtime: &mut Runtime,
+    ) -> Result<LoxValue, RuntimeError> {
+        if let Some(value) = instance.borrow().fields.get(name.symbol()) {
+            return Ok(value.clone());
+        }
+
+        let method = instance.borrow().class.find_method(name.symbol());
+        match method {
+            Some(method) => Ok(LoxValue::Function(
+                method.bind(LoxValue::Instance(Rc::clone(instance)), runtime),
+            )),
+            None => Err(RuntimeError::new(
+                name.span(),
+                String::from(name.lexeme()),
+                RuntimeErrorType::UndefinedProperty,
+            )),
+        }
+    }
+
+    pub fn set(&mut self, name: &Token, value: LoxValue) {
+        self.fields.insert(name.symbol().clone(), value);
+    }
+
+    pub(crate) fn class(&self) -> &Rc<LoxClass> {
+        &self.class
+    }
+
+    pub(crate) fn fields(&self) -> impl Iterator<Item = &LoxValue> {
+        self.fields.values()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.fields.clear();
+    }
+}
+
+// instances only compare equal to themselves
+impl PartialEq for LoxInstance {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Debug for LoxInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} instance", self.class.name())
+    }
+}
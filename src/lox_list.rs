@@ -0,0 +1,268 @@
+use std::{
+    cell::RefCell,
+    fmt::{self, Debug},
+    ops::Range,
+    rc::Rc,
+};
+
+use crate::{
+    errors::{RuntimeError, RuntimeErrorType},
+    lox_value::{self, LoxValue},
+    native_function::NativeFunction,
+    runtime::Runtime,
+    token::{Span, Token},
+};
+
+// The methods lists have, the same in both backends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListMethod {
+    Push,
+    Pop,
+    Len,
+    Insert,
+    Remove,
+    Slice,
+}
+
+impl ListMethod {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "push" => Some(Self::Push),
+            "pop" => Some(Self::Pop),
+            "len" => Some(Self::Len),
+            "insert" => Some(Self::Insert),
+            "remove" => Some(Self::Remove),
+            "slice" => Some(Self::Slice),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Push => "push",
+            Self::Pop => "pop",
+            Self::Len => "len",
+            Self::Insert => "insert",
+            Self::Remove => "remove",
+            Self::Slice => "slice",
+        }
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Self::Pop | Self::Len => 0,
+            Self::Push | Self::Remove => 1,
+            Self::Insert | Self::Slice => 2,
+        }
+    }
+}
+
+// Where `index` points in a list of `len` elements, counting back from the
+// end when it is negative. With `past_end` the index just after the last
+// element is allowed too, for inserting at the end.
+pub fn position(
+    index: f64,
+    len: usize,
+    past_end: bool,
+) -> Result<usize, (RuntimeErrorType, String)> {
+    if index.fract() != 0.0 {
+        return Err((RuntimeErrorType::InvalidIndex, index.to_string()));
+    }
+
+    let position = if index < 0.0 {
+        index + len as f64
+    } else {
+        index
+    };
+    let limit = if past_end { len + 1 } else { len };
+    if position < 0.0 || position >= limit as f64 {
+        return Err((
+            RuntimeErrorType::IndexOutOfBounds,
+            format!("{} for length {}", index, len),
+        ));
+    }
+    Ok(position as usize)
+}
+
+// The elements `slice(start, end)` covers. Negative bounds count back from the
+// end, bounds past either end are clamped to it and a missing end means the
+// rest of the list.
+pub fn slice_range(
+    start: f64,
+    end: Option<f64>,
+    len: usize,
+) -> Result<Range<usize>, (RuntimeErrorType, String)> {
+    let clamp = |index: f64| {
+        if index.fract() != 0.0 {
+            return Err((RuntimeErrorType::InvalidIndex, index.to_string()));
+        }
+        let position = if index < 0.0 {
+            index + len as f64
+        } else {
+            index
+        };
+        Ok(position.clamp(0.0, len as f64) as usize)
+    };
+
+    let start = clamp(start)?;
+    let end = match end {
+        Some(end) => clamp(end)?,
+        None => len,
+    };
+    Ok(start..end.max(start))
+}
+
+pub struct LoxList {
+    elements: Vec<LoxValue>,
+}
+
+impl LoxList {
+    pub fn new(elements: Vec<LoxValue>) -> Self {
+        Self { elements }
+    }
+
+    pub fn elements(&self) -> &[LoxValue] {
+        &self.elements
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.elements.clear();
+    }
+
+    pub fn get(&self, index: &LoxValue, span: Span) -> Result<LoxValue, RuntimeError> {
+        let position = self.position(index, span, false)?;
+        Ok(self.elements[position].clone())
+    }
+
+    pub fn set(
+        &mut self,
+        index: &LoxValue,
+        value: LoxValue,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        let position = self.position(index, span, false)?;
+        self.elements[position] = value;
+        Ok(())
+    }
+
+    // takes the shared list rather than `&self` so the method can hold on to it
+    pub fn method(list: &Rc<RefCell<LoxList>>, name: &Token) -> Result<LoxValue, RuntimeError> {
+        let Some(method) = ListMethod::from_name(name.lexeme()) else {
+            return Err(RuntimeError::new(
+                name.span(),
+                String::from(name.lexeme()),
+                RuntimeErrorType::UndefinedProperty,
+            ));
+        };
+
+        let list = Rc::clone(list);
+        let function = NativeFunction::new(
+            method.name(),
+            method.arity(),
+            move |arguments, span, runtime| {
+                list.borrow_mut().call(method, arguments, span, runtime)
+            },
+        );
+        Ok(LoxValue::NativeFunction(Rc::new(function)))
+    }
+
+    // the arguments have already been checked against the method's arity
+    fn call(
+        &mut self,
+        method: ListMethod,
+        mut arguments: Vec<LoxValue>,
+        span: Span,
+        runtime: &mut Runtime,
+    ) -> Result<LoxValue, RuntimeError> {
+        match method {
+            ListMethod::Push => {
+                self.elements.append(&mut arguments);
+                Ok(LoxValue::Nil)
+            }
+            ListMethod::Pop => self.elements.pop().ok_or_else(|| {
+                RuntimeError::new(
+                    span,
+                    String::from("pop from an empty list"),
+                    RuntimeErrorType::IndexOutOfBounds,
+                )
+            }),
+            ListMethod::Len => Ok(LoxValue::Number(self.elements.len() as f64)),
+            ListMethod::Insert => {
+                let value = arguments.pop().unwrap_or(LoxValue::Nil);
+                let position = self.position(&arguments[0], span, true)?;
+                self.elements.insert(position, value);
+                Ok(LoxValue::Nil)
+            }
+            ListMethod::Remove => {
+                let position = self.position(&arguments[0], span, false)?;
+                Ok(self.elements.remove(position))
+            }
+            ListMethod::Slice => {
+                let bound = |value: &LoxValue| match value {
+                    LoxValue::Number(n) => Ok(Some(*n)),
+                    LoxValue::Nil => Ok(None),
+                    value => Err(invalid_index(value, span)),
+                };
+                let (start, end) = (bound(&arguments[0])?, bound(&arguments[1])?);
+                let range = slice_range(start.unwrap_or(0.0), end, self.elements.len())
+                    .map_err(|(error_type, cause)| RuntimeError::new(span, cause, error_type))?;
+                let slice = LoxList::new(self.elements[range].to_vec());
+                Ok(LoxValue::List(runtime.collector().list(slice)))
+            }
+        }
+    }
+
+    fn position(
+        &self,
+        index: &LoxValue,
+        span: Span,
+        past_end: bool,
+    ) -> Result<usize, RuntimeError> {
+        let LoxValue::Number(index) = index else {
+            return Err(invalid_index(index, span));
+        };
+        position(*index, self.elements.len(), past_end)
+            .map_err(|(error_type, cause)| RuntimeError::new(span, cause, error_type))
+    }
+}
+
+fn invalid_index(index: &LoxValue, span: Span) -> RuntimeError {
+    RuntimeError::new(span, index.to_string(), RuntimeErrorType::InvalidIndex)
+}
+
+// Lists show their elements with strings quoted. A list inside itself shows
+// as `[...]` where it recurs.
+pub(crate) fn write_list(
+    f: &mut fmt::Formatter<'_>,
+    list: &RefCell<LoxList>,
+    enclosing: &mut Vec<*const ()>,
+) -> fmt::Result {
+    let pointer = list as *const RefCell<LoxList> as *const ();
+    if enclosing.contains(&pointer) {
+        return write!(f, "[...]");
+    }
+
+    enclosing.push(pointer);
+    write!(f, "[")?;
+    for (i, element) in list.borrow().elements.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        lox_value::write_element(f, element, enclosing)?;
+    }
+    enclosing.pop();
+    write!(f, "]")
+}
+
+// lists only compare equal to themselves
+impl PartialEq for LoxList {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Debug for LoxList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<list of {}>", self.elements.len())
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{self, Debug},
+    hash::Hash,
+    rc::Rc,
+};
+
+use crate::{
+    errors::{RuntimeError, RuntimeErrorType},
+    lox_list::LoxList,
+    lox_value::{self, LoxValue},
+    native_function::NativeFunction,
+    runtime::Runtime,
+    symbol::Symbol,
+    token::{Span, Token},
+};
+
+// The methods maps have, the same in both backends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapMethod {
+    Keys,
+    Values,
+    Has,
+    Remove,
+}
+
+impl MapMethod {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "keys" => Some(Self::Keys),
+            "values" => Some(Self::Values),
+            "has" => Some(Self::Has),
+            "remove" => Some(Self::Remove),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Keys => "keys",
+            Self::Values => "values",
+            Self::Has => "has",
+            Self::Remove => "remove",
+        }
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Self::Keys | Self::Values => 0,
+            Self::Has | Self::Remove => 1,
+        }
+    }
+}
+
+// The values a map can be keyed by, with strings held however the backend
+// holds them. Lists, maps, functions, classes and instances can change or
+// only equal themselves, so they aren't keys.
+//
+// Numbers are keyed by their bits once -0 has been made 0 and every NaN the
+// same NaN. So 0 and -0 are one key, as they are equal, and NaN is one key
+// too even though it doesn't equal itself with `==`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey<S> {
+    Nil,
+    Bool(bool),
+    Number(u64),
+    Str(S),
+}
+
+impl<S> MapKey<S> {
+    pub fn number(n: f64) -> Self {
+        let n = if n == 0.0 {
+            0.0
+        } else if n.is_nan() {
+            f64::NAN
+        } else {
+            n
+        };
+        MapKey::Number(n.to_bits())
+    }
+}
+
+// A hash map that remembers the order keys were first inserted in, which is
+// the order maps print in and `keys` and `values` return.
+pub struct OrderedMap<S, V> {
+    positions: HashMap<MapKey<S>, usize>, // where each key's entry is in `entries`
+    entries: Vec<(MapKey<S>, V)>,
+}
+
+impl<S: Clone + Eq + Hash, V> OrderedMap<S, V> {
+    pub fn new() -> Self {
+        Self {
+            positions: HashMap::new(),
+            entries: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &MapKey<S>) -> Option<&V> {
+        let position = *self.positions.get(key)?;
+        Some(&self.entries[position].1)
+    }
+
+    pub fn contains_key(&self, key: &MapKey<S>) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    // a key that is already there keeps its place
+    pub fn insert(&mut self, key: MapKey<S>, value: V) {
+        match self.positions.get(&key) {
+            Some(&position) => self.entries[position].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &MapKey<S>) -> Option<V> {
+        let position = self.positions.remove(key)?;
+        let (_, value) = self.entries.remove(position);
+        for later in self.positions.values_mut() {
+            if *later > position {
+                *later -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&MapKey<S>, &V)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
+
+impl<S: Clone + Eq + Hash, V> Default for OrderedMap<S, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct LoxMap {
+    entries: OrderedMap<Symbol, LoxValue>,
+}
+
+impl LoxMap {
+    pub fn new() -> Self {
+        Self {
+            entries: OrderedMap::new(),
+        }
+    }
+
+    pub fn entries(&self) -> &OrderedMap<Symbol, LoxValue> {
+        &self.entries
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries = OrderedMap::new();
+    }
+
+    pub fn get(&self, key: &LoxValue, span: Span) -> Result<LoxValue, RuntimeError> {
+        match self.entries.get(&map_key(key, span)?) {
+            Some(value) => Ok(value.clone()),
+            None => Err(RuntimeError::new(
+                span,
+                key.to_string(),
+                RuntimeErrorType::UndefinedKey,
+            )),
+        }
+    }
+
+    pub fn set(&mut self, key: &LoxValue, value: LoxValue, span: Span) -> Result<(), RuntimeError> {
+        self.entries.insert(map_key(key, span)?, value);
+        Ok(())
+    }
+
+    // takes the shared map rather than `&self` so the method can hold on to it
+    pub fn method(map: &Rc<RefCell<LoxMap>>, name: &Token) -> Result<LoxValue, RuntimeError> {
+        let Some(method) = MapMethod::from_name(name.lexeme()) else {
+            return Err(RuntimeError::new(
+                name.span(),
+                String::from(name.lexeme()),
+                RuntimeErrorType::UndefinedProperty,
+            ));
+        };
+
+        let map = Rc::clone(map);
+        let function = NativeFunction::new(
+            method.name(),
+            method.arity(),
+            move |arguments, span, runtime| map.borrow_mut().call(method, arguments, span, runtime),
+        );
+        Ok(LoxValue::NativeFunction(Rc::new(function)))
+    }
+
+    // the arguments have already been checked against the method's arity
+    fn call(
+        &mut self,
+        method: MapMethod,
+        arguments: Vec<LoxValue>,
+        span: Span,
+        runtime: &mut Runtime,
+    ) -> Result<LoxValue, RuntimeError> {
+        let mut list = |values| LoxValue::List(runtime.collector().list(LoxList::new(values)));
+        match method {
+            MapMethod::Keys => {
+                let keys = self.entries.iter().map(|(key, _)| key_value(key));
+                Ok(list(keys.collect()))
+            }
+            MapMethod::Values => {
+                let values = self.entries.iter().map(|(_, value)| value.clone());
+                Ok(list(values.collect()))
+            }
+            MapMethod::Has => Ok(LoxValue::Bool(
+                self.entries.contains_key(&map_key(&arguments[0], span)?),
+            )),
+            // removing a key that isn't there does nothing
+            MapMethod::Remove => {
+                let removed = self.entries.remove(&map_key(&arguments[0], span)?);
+                Ok(removed.unwrap_or(LoxValue::Nil))
+            }
+        }
+    }
+}
+
+impl Default for LoxMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn map_key(value: &LoxValue, span: Span) -> Result<MapKey<Symbol>, RuntimeError> {
+    match value {
+        LoxValue::Nil => Ok(MapKey::Nil),
+        LoxValue::Bool(b) => Ok(MapKey::Bool(*b)),
+        LoxValue::Number(n) => Ok(MapKey::number(*n)),
+        LoxValue::Str(s) => Ok(MapKey::Str(s.clone())),
+        value => Err(RuntimeError::new(
+            span,
+            value.to_string(),
+            RuntimeErrorType::UnhashableKey,
+        )),
+    }
+}
+
+fn key_value(key: &MapKey<Symbol>) -> LoxValue {
+    match key {
+        MapKey::Nil => LoxValue::Nil,
+        MapKey::Bool(b) => LoxValue::Bool(*b),
+        MapKey::Number(bits) => LoxValue::Number(f64::from_bits(*bits)),
+        MapKey::Str(s) => LoxValue::Str(s.clone()),
+    }
+}
+
+// Maps show their entries the way lists show their elements, as `{...}`
+// where a map recurs inside itself.
+pub(crate) fn write_map(
+    f: &mut fmt::Formatter<'_>,
+    map: &RefCell<LoxMap>,
+    enclosing: &mut Vec<*const ()>,
+) -> fmt::Result {
+    let pointer = map as *const RefCell<LoxMap> as *const ();
+    if enclosing.contains(&pointer) {
+        return write!(f, "{{...}}");
+    }
+
+    enclosing.push(pointer);
+    write!(f, "{{")?;
+    for (i, (key, value)) in map.borrow().entries.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        lox_value::write_element(f, &key_value(key), enclosing)?;
+        write!(f, ": ")?;
+        lox_value::write_element(f, value, enclosing)?;
+    }
+    enclosing.pop();
+    write!(f, "}}")
+}
+
+// maps only compare equal to themselves
+impl PartialEq for LoxMap {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Debug for LoxMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<map of {}>", self.entries.len())
+    }
+}
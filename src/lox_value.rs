@@ -1,20 +1,79 @@
-use std::fmt::Display;
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+use crate::{
+    lox_callable::LoxCallable,
+    lox_class::LoxClass,
+    lox_function::LoxFunction,
+    lox_instance::LoxInstance,
+    lox_list::{self, LoxList},
+    lox_map::{self, LoxMap},
+    native_function::NativeFunction,
+    symbol::Symbol,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum LoxValue {
     Number(f64),
-    Str(String),
+    Str(Symbol),
     Bool(bool),
+    Function(Rc<LoxFunction>),
+    NativeFunction(Rc<NativeFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    List(Rc<RefCell<LoxList>>),
+    Map(Rc<RefCell<LoxMap>>),
     Nil,
 }
 
+impl LoxValue {
+    // false and nil are falsey, everything else is truthy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, LoxValue::Bool(false) | LoxValue::Nil)
+    }
+
+    pub fn as_callable(&self) -> Option<Rc<dyn LoxCallable>> {
+        match self {
+            LoxValue::Function(function) => Some(Rc::clone(function) as Rc<dyn LoxCallable>),
+            LoxValue::NativeFunction(function) => Some(Rc::clone(function) as Rc<dyn LoxCallable>),
+            LoxValue::Class(class) => Some(Rc::clone(class) as Rc<dyn LoxCallable>),
+            _ => None,
+        }
+    }
+}
+
 impl Display for LoxValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Number(n) => write!(f, "{}", n),
             Self::Str(s) => write!(f, "{}", s),
             Self::Bool(b) => write!(f, "{}", b),
+            Self::Function(function) => write!(f, "<fn {}>", function.name()),
+            Self::NativeFunction(function) => write!(f, "{:?}", function),
+            Self::Class(class) => write!(f, "{}", class.name()),
+            Self::Instance(instance) => write!(f, "{:?}", instance.borrow()),
+            Self::List(list) => lox_list::write_list(f, list, &mut vec![]),
+            Self::Map(map) => lox_map::write_map(f, map, &mut vec![]),
             Self::Nil => write!(f, "nil"),
         }
     }
 }
+
+// Writes a value inside a list or map: strings are quoted and `enclosing`
+// holds the lists and maps being written around it, so one inside itself
+// isn't written forever.
+pub(crate) fn write_element(
+    f: &mut fmt::Formatter<'_>,
+    value: &LoxValue,
+    enclosing: &mut Vec<*const ()>,
+) -> fmt::Result {
+    match value {
+        LoxValue::Str(s) => write!(f, "\"{}\"", s),
+        LoxValue::List(list) => lox_list::write_list(f, list, enclosing),
+        LoxValue::Map(map) => lox_map::write_map(f, map, enclosing),
+        value => write!(f, "{}", value),
+    }
+}
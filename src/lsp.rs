@@ -0,0 +1,344 @@
+use std::{collections::HashMap, io, process};
+
+use rust_lox::{
+    errors::DiagnosticKind,
+    frontend::{self, Analysis},
+    linter::LintConfig,
+    resolver::{Definition, DefinitionKind},
+    stmt::Stmt,
+    token::Span,
+};
+
+use crate::json::{self, Json};
+
+// JSON-RPC error codes for messages that aren't JSON and for requests the
+// server doesn't implement.
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+// Serves editor requests over stdin and stdout until the client exits. Every
+// open document is analyzed again whenever it changes or is asked about.
+pub fn run() {
+    let mut documents: HashMap<String, String> = HashMap::new();
+    let mut shutdown = false;
+
+    loop {
+        let message = match json::read_message(&mut io::stdin().lock()) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            // the id of a message that couldn't be read is unknown, which
+            // JSON-RPC answers with a null one
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                eprintln!("Malformed message: {}", e);
+                respond_error(&Json::Null, PARSE_ERROR, &e.to_string());
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Failed to read message: {}", e);
+                break;
+            }
+        };
+        let id = message.get("id");
+        let params = message.get("params");
+        let uri = params
+            .get("textDocument")
+            .get("uri")
+            .as_str()
+            .map(String::from);
+
+        match message.get("method").as_str().unwrap_or_default() {
+            "initialize" => respond(id, capabilities()),
+            "shutdown" => {
+                shutdown = true;
+                respond(id, Json::Null);
+            }
+            "exit" => process::exit(if shutdown { 0 } else { 1 }),
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").get("text").as_str();
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    publish_diagnostics(&uri, text);
+                    documents.insert(uri, String::from(text));
+                }
+            }
+            "textDocument/didChange" => {
+                // documents are always synced whole, so the last change is the new text
+                let changes = params.get("contentChanges").as_array();
+                let text = changes.last().and_then(|c| c.get("text").as_str());
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    publish_diagnostics(&uri, text);
+                    documents.insert(uri, String::from(text));
+                }
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = uri {
+                    documents.remove(&uri);
+                    notify(
+                        "textDocument/publishDiagnostics",
+                        Json::object([("uri", uri.into()), ("diagnostics", Json::Array(vec![]))]),
+                    );
+                }
+            }
+            method @ ("textDocument/definition"
+            | "textDocument/references"
+            | "textDocument/hover"
+            | "textDocument/documentSymbol") => {
+                let result = match uri.as_ref().and_then(|uri| documents.get(uri)) {
+                    Some(text) => {
+                        let document = Document::new(uri.as_deref().unwrap_or_default(), text);
+                        document.answer(method, params)
+                    }
+                    None => Json::Null,
+                };
+                respond(id, result);
+            }
+            method if *id != Json::Null => respond_error(
+                id,
+                METHOD_NOT_FOUND,
+                &format!("Unsupported request '{}'", method),
+            ),
+            // notifications we don't care about
+            _ => {}
+        }
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object([
+        (
+            "capabilities",
+            Json::object([
+                ("textDocumentSync", 1u32.into()), // full text on every change
+                ("definitionProvider", true.into()),
+                ("referencesProvider", true.into()),
+                ("hoverProvider", true.into()),
+                ("documentSymbolProvider", true.into()),
+            ]),
+        ),
+        ("serverInfo", Json::object([("name", "rust_lox".into())])),
+    ])
+}
+
+fn send(mut members: Vec<(String, Json)>) {
+    members.insert(0, (String::from("jsonrpc"), "2.0".into()));
+    // stdout going away means the editor hung up
+    if json::write_message(&mut io::stdout(), &Json::Object(members)).is_err() {
+        process::exit(1);
+    }
+}
+
+fn respond(id: &Json, result: Json) {
+    send(vec![
+        (String::from("id"), id.clone()),
+        (String::from("result"), result),
+    ]);
+}
+
+fn respond_error(id: &Json, code: i32, message: &str) {
+    let error = Json::object([
+        ("code", Json::Number(code as f64)),
+        ("message", message.into()),
+    ]);
+    send(vec![
+        (String::from("id"), id.clone()),
+        (String::from("error"), error),
+    ]);
+}
+
+fn notify(method: &str, params: Json) {
+    send(vec![
+        (String::from("method"), method.into()),
+        (String::from("params"), params),
+    ]);
+}
+
+fn publish_diagnostics(uri: &str, text: &str) {
+    let document = Document::new(uri, text);
+    let diagnostics = document.diagnostics();
+    notify(
+        "textDocument/publishDiagnostics",
+        Json::object([("uri", uri.into()), ("diagnostics", diagnostics)]),
+    );
+}
+
+// An open file along with what the front end found in it.
+struct Document<'a> {
+    uri: &'a str,
+    text: &'a str,
+    analysis: Analysis,
+}
+
+impl<'a> Document<'a> {
+    fn new(uri: &'a str, text: &'a str) -> Self {
+        Self {
+            uri,
+            text,
+            analysis: frontend::analyze(text, LintConfig::default()),
+        }
+    }
+
+    fn answer(&self, method: &str, params: &Json) -> Json {
+        if method == "textDocument/documentSymbol" {
+            return self.symbols();
+        }
+
+        let Some((definition, span)) = self.definition_at(params.get("position")) else {
+            return Json::Null;
+        };
+        match method {
+            "textDocument/definition" => self.location(definition.span),
+            "textDocument/references" => {
+                let include_declaration = params
+                    .get("context")
+                    .get("includeDeclaration")
+                    .as_bool()
+                    .unwrap_or(true);
+                let mut locations = vec![];
+                if include_declaration {
+                    locations.push(self.location(definition.span));
+                }
+                for reference in &definition.references {
+                    locations.push(self.location(*reference));
+                }
+                locations.into()
+            }
+            "textDocument/hover" => Json::object([
+                (
+                    "contents",
+                    Json::object([
+                        ("kind", "markdown".into()),
+                        (
+                            "value",
+                            format!("```lox\n{}\n```", definition.detail).into(),
+                        ),
+                    ]),
+                ),
+                ("range", self.range(span)),
+            ]),
+            _ => Json::Null,
+        }
+    }
+
+    // the definition of the name under the cursor, whether it is being
+    // declared or referred to there, and the span of that name
+    fn definition_at(&self, position: &Json) -> Option<(&Definition, Span)> {
+        let offset = self.offset(position)?;
+        let covers = |span: &Span| span.offset <= offset && offset <= span.end();
+
+        self.analysis.definitions.iter().find_map(|definition| {
+            if covers(&definition.span) {
+                return Some((definition, definition.span));
+            }
+            let reference = definition.references.iter().find(|r| covers(r))?;
+            Some((definition, *reference))
+        })
+    }
+
+    // functions, classes, methods and global variables, in source order
+    fn symbols(&self) -> Json {
+        let globals: Vec<Span> = self
+            .analysis
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Stmt::Var { name, .. } => Some(name.span()),
+                _ => None,
+            })
+            .collect();
+
+        let symbols: Vec<Json> = self
+            .analysis
+            .definitions
+            .iter()
+            .filter_map(|definition| {
+                let kind: u32 = match definition.kind {
+                    DefinitionKind::Class => 5,
+                    DefinitionKind::Method => 6,
+                    DefinitionKind::Function => 12,
+                    DefinitionKind::Variable if globals.contains(&definition.span) => 13,
+                    _ => return None,
+                };
+                let mut symbol = vec![
+                    (String::from("name"), definition.name.as_str().into()),
+                    (String::from("kind"), kind.into()),
+                    (
+                        String::from("location"),
+                        self.location(definition.declaration),
+                    ),
+                ];
+                if let Some(container) = &definition.container {
+                    symbol.push((String::from("containerName"), container.as_str().into()));
+                }
+                Some(Json::Object(symbol))
+            })
+            .collect();
+        symbols.into()
+    }
+
+    // errors from the front end, or the linter's warnings once there are none
+    fn diagnostics(&self) -> Json {
+        let diagnostics: Vec<Json> = self
+            .analysis
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let code = match diagnostic.kind {
+                    DiagnosticKind::Scan => "scan",
+                    DiagnosticKind::Parse => "parse",
+                    DiagnosticKind::Resolve => "resolve",
+                    DiagnosticKind::Compile => "compile",
+                    DiagnosticKind::Runtime => "runtime",
+                    DiagnosticKind::Lint(rule) => rule.name(),
+                };
+                let severity: u32 = if diagnostic.is_warning() { 2 } else { 1 };
+                Json::object([
+                    ("range", self.range(diagnostic.span)),
+                    ("severity", severity.into()), // 1 error, 2 warning
+                    ("code", code.into()),
+                    ("source", "lox".into()),
+                    ("message", diagnostic.message.as_str().into()),
+                ])
+            })
+            .collect();
+        diagnostics.into()
+    }
+
+    fn location(&self, span: Span) -> Json {
+        Json::object([("uri", self.uri.into()), ("range", self.range(span))])
+    }
+
+    fn range(&self, span: Span) -> Json {
+        Json::object([
+            ("start", self.position(span.offset)),
+            ("end", self.position(span.end())),
+        ])
+    }
+
+    // Editors count lines from zero and characters in UTF-16 code units,
+    // spans count bytes.
+    fn position(&self, offset: usize) -> Json {
+        let before = &self.text[..offset.min(self.text.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line = before.matches('\n').count();
+        let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+        Json::object([("line", line.into()), ("character", character.into())])
+    }
+
+    fn offset(&self, position: &Json) -> Option<usize> {
+        let line = position.get("line").as_u32()? as usize;
+        let character = position.get("character").as_u32()? as usize;
+
+        let line_start = if line == 0 {
+            0
+        } else {
+            self.text.match_indices('\n').nth(line - 1)?.0 + 1
+        };
+        let mut units = 0;
+        for (i, c) in self.text[line_start..].char_indices() {
+            if units >= character || c == '\n' {
+                return Some(line_start + i);
+            }
+            units += c.len_utf16();
+        }
+        Some(self.text.len())
+    }
+}
@@ -1,29 +1,135 @@
-use std::env::args;
-
-mod ast_printable;
-mod environment;
-mod errors;
-mod expr;
-mod interpretable;
-mod lox_value;
-mod parser;
+use std::{env::args, process, thread};
+
+mod dap;
+mod debug_prompt;
+mod json;
+mod line_editor;
+mod lsp;
 mod runner;
-mod scanner;
-mod stmt;
-mod token;
+
+use runner::{Backend, Dump, Options};
+use rust_lox::linter::{LintConfig, Rule};
+
+// Lox calls nest up to 1023 deep in either backend. The tree-walker recurses
+// natively for each one, which takes more stack than a main thread gets when
+// it is built without optimizations, so everything runs on a bigger one.
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
 
 fn main() {
+    let session = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("failed to start the interpreter thread");
+    // a panic has already been reported by the time it is joined
+    if session.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn run() {
+    let arguments: Vec<String> = args().skip(1).collect();
+
+    // the protocols own stdout and `fmt --check` is meant for scripts, so
+    // nothing else may be printed first
+    match arguments.first().map(String::as_str) {
+        Some("dap") if arguments.len() == 1 => return dap::run(),
+        Some("lsp") if arguments.len() == 1 => return lsp::run(),
+        Some("lint") => {
+            let mut config = LintConfig::default();
+            let mut paths = vec![];
+            for argument in &arguments[1..] {
+                if !lint_flag(argument, &mut config) {
+                    paths.push(argument);
+                }
+            }
+            if paths.is_empty() || paths.iter().any(|p| p.starts_with("--")) {
+                usage();
+            }
+            return runner::lint_files(&paths, config);
+        }
+        Some("fmt") => {
+            let check = arguments[1..].iter().any(|a| a == "--check");
+            let paths: Vec<&String> = arguments[1..].iter().filter(|a| *a != "--check").collect();
+            if paths.is_empty() || paths.iter().any(|p| p.starts_with("--")) {
+                usage();
+            }
+            return runner::format_files(&paths, check);
+        }
+        _ => {}
+    }
+
     println!("Hello, rust_lox!");
-    let arguments: Vec<String> = args().collect();
-
-    if arguments.len() > 2 {
-        std::process::exit(64);
-    } else if arguments.len() == 2 {
-        if let Err(e) = runner::run_file(arguments[1].clone()) {
-            eprintln!("{}", e);
-            std::process::exit(99);
+
+    if let Some("debug") = arguments.first().map(String::as_str) {
+        match &arguments[1..] {
+            [path] => runner::debug_file(path),
+            _ => usage(),
         }
+        return;
+    }
+
+    let mut options = Options::default();
+    let mut paths = vec![];
+
+    for argument in arguments {
+        match argument.as_str() {
+            "--vm" => options.backend = Backend::Vm,
+            "--gc-stress" => options.gc.stress = true,
+            "--dump=tokens" => options.dump = Some(Dump::Tokens),
+            "--dump=ast" => options.dump = Some(Dump::Ast),
+            "--dump=bytecode" => options.dump = Some(Dump::Bytecode),
+            flag if lint_flag(flag, &mut options.lint) => {}
+            flag if flag.starts_with("--") => usage(),
+            _ => paths.push(argument),
+        }
+    }
+
+    match paths.as_slice() {
+        [] => runner::run_prompt(options),
+        [path] => runner::run_file(path, options),
+        _ => usage(),
+    }
+}
+
+// `--allow=<rule>` and `--warn=<rule>` turn a lint rule off or back on,
+// `--allow=all` silences the linter
+fn lint_flag(argument: &str, config: &mut LintConfig) -> bool {
+    if argument == "--allow=all" {
+        config.disable_all();
+        return true;
+    }
+
+    let (allow, name) = if let Some(name) = argument.strip_prefix("--allow=") {
+        (true, name)
+    } else if let Some(name) = argument.strip_prefix("--warn=") {
+        (false, name)
+    } else {
+        return false;
+    };
+
+    let Some(rule) = Rule::from_name(name) else {
+        let names: Vec<&str> = Rule::ALL.iter().map(|rule| rule.name()).collect();
+        eprintln!(
+            "Unknown lint rule '{}', expected one of: {}",
+            name,
+            names.join(", ")
+        );
+        process::exit(64);
+    };
+    if allow {
+        config.disable(rule);
     } else {
-        runner::run_prompt()
+        config.enable(rule);
     }
+    true
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: rust_lox [--vm] [--gc-stress] [--dump=tokens|ast|bytecode] [--allow=<rule>] [--warn=<rule>] [script]");
+    eprintln!("       rust_lox debug <script>");
+    eprintln!("       rust_lox dap");
+    eprintln!("       rust_lox lsp");
+    eprintln!("       rust_lox fmt [--check] <script>...");
+    eprintln!("       rust_lox lint [--allow=<rule>] [--warn=<rule>] <script>...");
+    process::exit(64);
 }
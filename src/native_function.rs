@@ -0,0 +1,62 @@
+use std::{fmt::Debug, rc::Rc};
+
+use crate::{
+    errors::RuntimeError, lox_callable::LoxCallable, lox_value::LoxValue, runtime::Runtime,
+    token::Span,
+};
+
+type NativeFn = dyn Fn(Vec<LoxValue>, Span, &mut Runtime) -> Result<LoxValue, RuntimeError>;
+
+// A function implemented in Rust: a method of a built-in type, or one the
+// host defined with `Interpreter::define_native`.
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(
+        name: &str,
+        arity: usize,
+        function: impl Fn(Vec<LoxValue>, Span, &mut Runtime) -> Result<LoxValue, RuntimeError> + 'static,
+    ) -> Self {
+        Self {
+            name: String::from(name),
+            arity,
+            function: Box::new(function),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl LoxCallable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(
+        self: Rc<Self>,
+        arguments: Vec<LoxValue>,
+        span: Span,
+        runtime: &mut Runtime,
+    ) -> Result<LoxValue, RuntimeError> {
+        (self.function)(arguments, span, runtime)
+    }
+}
+
+// native functions only compare equal to themselves
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
@@ -1,111 +1,403 @@
+use std::{cell::Cell, rc::Rc};
+
 use crate::{
-    errors,
+    errors::{DiagnosticKind, Diagnostics},
     expr::Expr,
-    stmt::Stmt,
-    token::{Token, TokenType},
+    stmt::{FunctionDeclaration, Stmt},
+    symbol::Symbol,
+    token::{Span, Token, TokenType},
 };
 
+const MAX_ARGUMENTS: usize = 255;
+
 struct ParseError {
     message: String,
-    line: u32,
+    span: Span,
 }
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    diagnostics: Diagnostics,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+        Parser {
+            tokens,
+            current: 0,
+            diagnostics: Diagnostics::new(),
+        }
+    }
+
+    pub fn take_diagnostics(&mut self) -> Diagnostics {
+        std::mem::take(&mut self.diagnostics)
     }
 
-    pub fn parse(&mut self) -> Option<Vec<Stmt>> {
+    // Parses as much of the program as possible. Every syntax error is recorded in the
+    // diagnostics and parsing resumes at the next statement, so the returned statements
+    // are the ones that parsed cleanly.
+    pub fn parse(&mut self) -> Vec<Stmt> {
         let mut statements: Vec<Stmt> = vec![];
         while !self.is_at_end() {
-            match self.declaration() {
-                Ok(s) => statements.push(s),
-                Err(e) => {
-                    errors::error(e.line, e.message);
-                    self.synchronize();
-                    break;
-                }
+            if let Some(statement) = self.declaration_or_recover() {
+                statements.push(statement);
+            }
+        }
+        statements
+    }
+
+    // Parses a single expression that must make up the whole input, for
+    // evaluating expressions typed into the debugger.
+    pub fn parse_expression(&mut self) -> Option<Expr> {
+        let result = self.expression().and_then(|expr| {
+            if self.is_at_end() {
+                Ok(expr)
+            } else {
+                Err(ParseError {
+                    message: String::from("Expect end of expression"),
+                    span: self.peek().span(),
+                })
+            }
+        });
+
+        match result {
+            Ok(expr) => Some(expr),
+            Err(e) => {
+                self.diagnostics
+                    .error(DiagnosticKind::Parse, e.span, e.message);
+                None
+            }
+        }
+    }
+
+    fn declaration_or_recover(&mut self) -> Option<Stmt> {
+        match self.declaration() {
+            Ok(statement) => Some(statement),
+            Err(e) => {
+                self.diagnostics
+                    .error(DiagnosticKind::Parse, e.span, e.message);
+                self.synchronize(e.span);
+                None
             }
         }
-        return Some(statements);
     }
 
     fn declaration(&mut self) -> Result<Stmt, ParseError> {
-        if self.match_tokens(&[TokenType::Var]) {
+        if self.match_tokens(&[TokenType::Class]) {
+            self.class_declaration()
+        } else if self.match_tokens(&[TokenType::Fun]) {
+            let start = self.previous().span();
+            Ok(Stmt::Function(Rc::new(self.function("function", start)?)))
+        } else if self.match_tokens(&[TokenType::Var]) {
             self.var_declaration()
         } else {
             self.statement()
         }
     }
 
-    fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
-        let TokenType::Identifier(name) = self.peek().type_info() else {
-            return Err(ParseError {
-                message: String::from("Unexpected token: Expected identifier after keyword 'var'"),
-                line: self.previous().line(),
-            });
+    fn class_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span();
+        let name = self.consume_identifier("Expect class name")?;
+
+        let superclass = if self.match_tokens(&[TokenType::Less]) {
+            Some(Expr::Variable {
+                token: self.consume_identifier("Expect superclass name")?,
+                depth: Cell::new(None),
+            })
+        } else {
+            None
         };
 
-        let name = name.clone();
-        self.advance();
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body")?;
 
-        if !self.match_tokens(&[TokenType::Equal]) {
-            return Err(ParseError {
-                message: String::from("Unexpexted token: Expected '=' after variable identifier"),
-                line: self.previous().line(),
-            });
-        };
+        let mut methods = vec![];
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let start = self.peek().span();
+            methods.push(Rc::new(self.function("method", start)?));
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body")?;
+
+        Ok(Stmt::Class {
+            name,
+            superclass,
+            methods,
+            span: self.span_from(start),
+        })
+    }
 
-        match self.expression() {
-            Ok(initializer) => {
-                if let Err(e) = self.consume(
-                    TokenType::Semicolon,
-                    "Expected ';' after variable declaration",
-                ) {
-                    return Err(e);
+    fn function(&mut self, kind: &str, start: Span) -> Result<FunctionDeclaration, ParseError> {
+        let name = self.consume_identifier(&format!("Expect {} name", kind))?;
+        self.consume(
+            TokenType::LeftParen,
+            &format!("Expect '(' after {} name", kind),
+        )?;
+
+        let mut params = vec![];
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    return Err(ParseError {
+                        message: format!("Can't have more than {} parameters.", MAX_ARGUMENTS),
+                        span: self.peek().span(),
+                    });
+                }
+                params.push(self.consume_identifier("Expect parameter name")?);
+
+                if !self.match_tokens(&[TokenType::Comma]) {
+                    break;
                 }
-                Ok(Stmt::Var { name, initializer })
             }
-            Err(e) => Err(e),
         }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters")?;
+
+        self.consume(
+            TokenType::LeftBrace,
+            &format!("Expect '{{' before {} body", kind),
+        )?;
+        let body = self.block()?;
+
+        Ok(FunctionDeclaration {
+            name,
+            params,
+            body,
+            span: self.span_from(start),
+        })
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span();
+        let name = self.consume_identifier("Expected identifier after keyword 'var'")?;
+
+        let initializer = if self.match_tokens(&[TokenType::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(
+            TokenType::Semicolon,
+            "Expected ';' after variable declaration",
+        )?;
+        Ok(Stmt::Var {
+            name,
+            initializer,
+            span: self.span_from(start),
+        })
     }
 
     fn statement(&mut self) -> Result<Stmt, ParseError> {
-        if self.match_tokens(&[TokenType::Print]) {
+        if self.match_tokens(&[TokenType::For]) {
+            self.for_statement()
+        } else if self.match_tokens(&[TokenType::If]) {
+            self.if_statement()
+        } else if self.match_tokens(&[TokenType::Print]) {
             self.print_statement()
+        } else if self.match_tokens(&[TokenType::Return]) {
+            self.return_statement()
+        } else if self.match_tokens(&[TokenType::While]) {
+            self.while_statement()
+        } else if self.match_tokens(&[TokenType::LeftBrace]) {
+            let start = self.previous().span();
+            let statements = self.block()?;
+            Ok(Stmt::Block {
+                statements,
+                span: self.span_from(start),
+            })
         } else {
             self.expression_statement()
         }
     }
 
-    fn print_statement(&mut self) -> Result<Stmt, ParseError> {
-        let value = self.expression();
-        if let Err(e) = self.consume(TokenType::Semicolon, "Expect ';' after value") {
-            return Err(e);
+    // for loops are desugared into an equivalent while loop wrapped in a block
+    fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'")?;
+
+        let initializer = if self.match_tokens(&[TokenType::Semicolon]) {
+            None
+        } else if self.match_tokens(&[TokenType::Var]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
         };
 
-        Ok(Stmt::Print(value?))
+        let condition = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition")?;
+
+        let increment = if self.check(&TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses")?;
+
+        let mut body = self.statement()?;
+        let span = self.span_from(start);
+
+        if let Some(increment) = increment {
+            let increment_span = increment.span();
+            body = Stmt::Block {
+                statements: vec![
+                    body,
+                    Stmt::Expression {
+                        expression: increment,
+                        span: increment_span,
+                    },
+                ],
+                span,
+            };
+        }
+
+        let condition = condition.unwrap_or(Expr::Literal {
+            value: Token::new(TokenType::True, Symbol::intern("true"), start),
+        });
+        body = Stmt::While {
+            condition,
+            body: Box::new(body),
+            span,
+        };
+
+        if let Some(initializer) = initializer {
+            body = Stmt::Block {
+                statements: vec![initializer, body],
+                span,
+            };
+        }
+
+        Ok(body)
     }
 
-    fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
-        let expression = self.expression();
-        if let Err(e) = self.consume(TokenType::Semicolon, "Expect ';' after expression") {
-            return Err(e);
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after if condition")?;
+
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.match_tokens(&[TokenType::Else]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
         };
-        return Ok(Stmt::Expression(expression?));
+
+        Ok(Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+            span: self.span_from(start),
+        })
     }
 
-    fn expression_list(&mut self) -> Result<Expr, ParseError> {
-        return self.parse_binary(|s| s.expression(), &[TokenType::Comma]);
+    fn print_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span();
+        let expression = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after value")?;
+
+        Ok(Stmt::Print {
+            expression,
+            span: self.span_from(start),
+        })
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        self.consume(TokenType::Semicolon, "Expect ';' after return value")?;
+        Ok(Stmt::Return {
+            span: self.span_from(keyword.span()),
+            keyword,
+            value,
+        })
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous().span();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition")?;
+        let body = Box::new(self.statement()?);
+
+        Ok(Stmt::While {
+            condition,
+            body,
+            span: self.span_from(start),
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut statements: Vec<Stmt> = vec![];
+
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if let Some(statement) = self.declaration_or_recover() {
+                statements.push(statement);
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after block")?;
+        Ok(statements)
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.peek().span();
+        let expression = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after expression")?;
+        Ok(Stmt::Expression {
+            expression,
+            span: self.span_from(start),
+        })
     }
 
     fn expression(&mut self) -> Result<Expr, ParseError> {
-        return self.conditional();
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.conditional()?;
+
+        if self.match_tokens(&[TokenType::Equal]) {
+            let equals = self.previous().clone();
+            let value = self.assignment()?;
+
+            return match expr {
+                Expr::Variable { token, .. } => Ok(Expr::Assign {
+                    name: token,
+                    value: Box::new(value),
+                    depth: Cell::new(None),
+                }),
+                Expr::Get { object, name } => Ok(Expr::Set {
+                    object,
+                    name,
+                    value: Box::new(value),
+                }),
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => Ok(Expr::SetIndex {
+                    object,
+                    bracket,
+                    index,
+                    value: Box::new(value),
+                }),
+                _ => Err(ParseError {
+                    message: String::from("Invalid assignment target."),
+                    span: equals.span(),
+                }),
+            };
+        }
+
+        Ok(expr)
     }
 
     fn parse_binary(
@@ -120,7 +412,7 @@ impl Parser {
             let right = nxt(self)?;
             expr = Expr::Binary {
                 left: Box::new(expr),
-                operator: operator.clone(),
+                operator,
                 right: Box::new(right),
             };
         }
@@ -129,7 +421,7 @@ impl Parser {
     }
 
     fn conditional(&mut self) -> Result<Expr, ParseError> {
-        let equality_expr = self.equality();
+        let logical_expr = self.or();
 
         if self.match_tokens(&[TokenType::QuestionMark]) {
             let consequent = self.expression()?;
@@ -138,14 +430,42 @@ impl Parser {
                 let alternative = self.expression()?;
 
                 return Ok(Expr::Conditional {
-                    condition: Box::new(equality_expr?),
+                    condition: Box::new(logical_expr?),
                     consequent: Box::new(consequent),
                     alternative: Box::new(alternative),
                 });
             }
         }
 
-        equality_expr
+        logical_expr
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        self.parse_logical(|s| s.and(), &[TokenType::Or])
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        self.parse_logical(|s| s.equality(), &[TokenType::And])
+    }
+
+    fn parse_logical(
+        &mut self,
+        mut nxt: impl FnMut(&mut Self) -> Result<Expr, ParseError>,
+        tokens: &[TokenType],
+    ) -> Result<Expr, ParseError> {
+        let mut expr = nxt(self)?;
+
+        while self.match_tokens(tokens) {
+            let operator = self.previous().clone();
+            let right = nxt(self)?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
     }
 
     fn equality(&mut self) -> Result<Expr, ParseError> {
@@ -182,12 +502,69 @@ impl Parser {
                 let operator = self.previous().clone();
                 let right = self.unary()?;
                 Ok(Expr::Unary {
-                    operator: operator,
+                    operator,
                     right: Box::new(right),
                 })
             }
-            _ => self.primary(),
+            _ => self.call(),
+        }
+    }
+
+    fn call(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.match_tokens(&[TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_tokens(&[TokenType::Dot]) {
+                let name = self.consume_identifier("Expect property name after '.'")?;
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                };
+            } else if self.match_tokens(&[TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                self.consume(TokenType::RightBracket, "Expect ']' after index")?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket: self.previous().clone(),
+                    index: Box::new(index),
+                };
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, ParseError> {
+        let mut arguments = vec![];
+
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    return Err(ParseError {
+                        message: format!("Can't have more than {} arguments.", MAX_ARGUMENTS),
+                        span: self.peek().span(),
+                    });
+                }
+                arguments.push(self.expression()?);
+
+                if !self.match_tokens(&[TokenType::Comma]) {
+                    break;
+                }
+            }
         }
+
+        self.consume(TokenType::RightParen, "Expect ')' after arguments")?;
+        let paren = self.previous().clone();
+
+        Ok(Expr::Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
     }
 
     fn primary(&mut self) -> Result<Expr, ParseError> {
@@ -219,6 +596,7 @@ impl Parser {
             }
 
             TokenType::LeftParen => {
+                self.advance();
                 let expr = self.expression()?;
                 self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
                 Ok(Expr::Grouping {
@@ -233,17 +611,81 @@ impl Parser {
                 })
             }
 
+            TokenType::LeftBracket => {
+                let start = self.advance().span();
+                let mut elements = vec![];
+                if !self.check(&TokenType::RightBracket) {
+                    loop {
+                        elements.push(self.expression()?);
+                        if !self.match_tokens(&[TokenType::Comma]) {
+                            break;
+                        }
+                    }
+                }
+                self.consume(TokenType::RightBracket, "Expect ']' after list elements")?;
+                Ok(Expr::List {
+                    elements,
+                    span: self.span_from(start),
+                })
+            }
+
+            // a brace where an expression is expected; at the start of a
+            // statement it opens a block instead
+            TokenType::LeftBrace => {
+                let start = self.advance().span();
+                let mut entries = vec![];
+                if !self.check(&TokenType::RightBrace) {
+                    loop {
+                        let key = self.expression()?;
+                        self.consume(TokenType::Colon, "Expect ':' after map key")?;
+                        entries.push((key, self.expression()?));
+                        if !self.match_tokens(&[TokenType::Comma]) {
+                            break;
+                        }
+                    }
+                }
+                self.consume(TokenType::RightBrace, "Expect '}' after map entries")?;
+                Ok(Expr::Map {
+                    entries,
+                    span: self.span_from(start),
+                })
+            }
+
             TokenType::EndOfFile => Ok(Expr::Literal {
                 value: self.peek().clone(),
             }),
 
-            TokenType::Identifier(_) => Ok(Expr::Variable {
-                token: self.previous().clone(),
-            }),
+            TokenType::This => {
+                self.advance();
+                Ok(Expr::This {
+                    keyword: self.previous().clone(),
+                    depth: Cell::new(None),
+                })
+            }
+
+            TokenType::Super => {
+                self.advance();
+                let keyword = self.previous().clone();
+                self.consume(TokenType::Dot, "Expect '.' after 'super'")?;
+                let method = self.consume_identifier("Expect superclass method name")?;
+                Ok(Expr::Super {
+                    keyword,
+                    method,
+                    depth: Cell::new(None),
+                })
+            }
+
+            TokenType::Identifier(_) => {
+                self.advance();
+                Ok(Expr::Variable {
+                    token: self.previous().clone(),
+                    depth: Cell::new(None),
+                })
+            }
 
             _ => Err(ParseError {
                 message: format!("Unexpected token: {}.", self.tokens[self.current]),
-                line: self.peek().line(),
+                span: self.peek().span(),
             }),
         }
     }
@@ -255,11 +697,28 @@ impl Parser {
         } else {
             Err(ParseError {
                 message: format!("Unexpected token: {}.", message),
-                line: self.previous().line(),
+                span: self.previous().span(),
+            })
+        }
+    }
+
+    fn consume_identifier(&mut self, message: &str) -> Result<Token, ParseError> {
+        if let TokenType::Identifier(_) = self.peek().type_info() {
+            self.advance();
+            Ok(self.previous().clone())
+        } else {
+            Err(ParseError {
+                message: format!("Unexpected token: {}.", message),
+                span: self.peek().span(),
             })
         }
     }
 
+    // span from `start` through the most recently consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous().span())
+    }
+
     fn match_tokens(&mut self, tokens: &[TokenType]) -> bool {
         for token in tokens {
             if self.check(token) {
@@ -301,26 +760,47 @@ impl Parser {
         *self.peek().type_info() == TokenType::EndOfFile
     }
 
-    fn synchronize(&mut self) {
-        self.advance();
+    // Skips tokens until the start of the next statement. Braced regions opened while
+    // skipping are skipped whole so a broken declaration doesn't end its enclosing block.
+    // A keyword that starts a statement is kept when the error was found before it,
+    // as after a missing `;`. The token the error is at is always skipped, so
+    // parsing moves on.
+    fn synchronize(&mut self, error: Span) {
+        if self.at_statement_keyword() && self.peek().span() != error {
+            return;
+        }
+        let mut depth = self.skip_token(0);
 
         while !self.is_at_end() {
-            if *self.previous().type_info() == TokenType::Semicolon {
+            let at_boundary = *self.previous().type_info() == TokenType::Semicolon
+                || *self.peek().type_info() == TokenType::RightBrace
+                || self.at_statement_keyword();
+            if depth == 0 && at_boundary {
                 return;
             }
+            depth = self.skip_token(depth);
+        }
+    }
 
-            match self.peek().type_info() {
-                TokenType::Class
+    fn at_statement_keyword(&self) -> bool {
+        matches!(
+            self.peek().type_info(),
+            TokenType::Class
                 | TokenType::Fun
                 | TokenType::Var
                 | TokenType::For
                 | TokenType::If
                 | TokenType::While
                 | TokenType::Print
-                | TokenType::Return => return,
-                _ => {}
-            }
-            self.advance();
+                | TokenType::Return
+        )
+    }
+
+    fn skip_token(&mut self, depth: usize) -> usize {
+        match self.advance().type_info() {
+            TokenType::LeftBrace => depth + 1,
+            TokenType::RightBrace => depth.saturating_sub(1),
+            _ => depth,
         }
     }
 }
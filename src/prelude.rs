@@ -0,0 +1,70 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{interpreter::Interpreter, lox_value::LoxValue, symbol::Symbol};
+
+// The functions every script can call without defining them. The VM defines
+// the same ones in `bytecode::prelude`, sharing the helpers below.
+//
+// clock()  seconds since the Unix epoch
+// type(x)  the name of x's type: "nil", "bool", "number", "string",
+//          "function", "class", "instance", "list" or "map"
+// str(x)   x as `print` shows it
+// num(x)   x as a number, parsed if it is a string
+// len(x)   the number of characters, elements or entries in x
+pub fn define(interpreter: &mut Interpreter) {
+    interpreter.define_native("clock", 0, |_| Ok(LoxValue::Number(clock())));
+    interpreter.define_native("type", 1, |arguments| {
+        Ok(LoxValue::Str(Symbol::intern(type_name(&arguments[0]))))
+    });
+    interpreter.define_native("str", 1, |arguments| {
+        Ok(LoxValue::Str(Symbol::intern(&arguments[0].to_string())))
+    });
+    interpreter.define_native("num", 1, |arguments| match &arguments[0] {
+        LoxValue::Number(n) => Ok(LoxValue::Number(*n)),
+        LoxValue::Str(s) => parse_number(s).map(LoxValue::Number),
+        value => Err(not_a_number(&value.to_string())),
+    });
+    interpreter.define_native("len", 1, |arguments| {
+        let len = match &arguments[0] {
+            LoxValue::Str(s) => s.chars().count(),
+            LoxValue::List(list) => list.borrow().elements().len(),
+            LoxValue::Map(map) => map.borrow().entries().len(),
+            value => return Err(no_length(&value.to_string())),
+        };
+        Ok(LoxValue::Number(len as f64))
+    });
+}
+
+fn type_name(value: &LoxValue) -> &'static str {
+    match value {
+        LoxValue::Nil => "nil",
+        LoxValue::Bool(_) => "bool",
+        LoxValue::Number(_) => "number",
+        LoxValue::Str(_) => "string",
+        LoxValue::Function(_) | LoxValue::NativeFunction(_) => "function",
+        LoxValue::Class(_) => "class",
+        LoxValue::Instance(_) => "instance",
+        LoxValue::List(_) => "list",
+        LoxValue::Map(_) => "map",
+    }
+}
+
+pub(crate) fn clock() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64())
+}
+
+// surrounding whitespace is ignored
+pub(crate) fn parse_number(text: &str) -> Result<f64, String> {
+    text.trim().parse().map_err(|_| not_a_number(text))
+}
+
+// `shown` is the value as `print` shows it
+pub(crate) fn not_a_number(shown: &str) -> String {
+    format!("Can't convert to a number: {}", shown)
+}
+
+pub(crate) fn no_length(shown: &str) -> String {
+    format!("Only strings, lists and maps have a length: {}", shown)
+}
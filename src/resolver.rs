@@ -0,0 +1,484 @@
+use std::{cell::Cell, collections::HashMap, rc::Rc};
+
+use crate::{
+    errors::{DiagnosticKind, Diagnostics},
+    expr::Expr,
+    stmt::{FunctionDeclaration, Stmt},
+    symbol::Symbol,
+    token::{Span, Token},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DefinitionKind {
+    Variable,
+    Parameter,
+    Function,
+    Class,
+    Method,
+}
+
+// A declared name and every place it is read or assigned, for editor tooling.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: Symbol,
+    pub kind: DefinitionKind,
+    pub span: Span,                // the name where it is declared
+    pub declaration: Span,         // the whole declaration
+    pub detail: String,            // how the declaration reads, e.g. `fun add(a, b)`
+    pub container: Option<Symbol>, // the class a method belongs to
+    pub references: Vec<Span>,
+}
+
+// Walks the syntax tree once before it is interpreted, recording how many
+// environments up the chain each variable reference lives and reporting
+// scope errors the parser can't see.
+pub struct Resolver {
+    scopes: Vec<HashMap<Symbol, bool>>, // local scopes; the value marks whether the name is fully defined
+    current_function: FunctionType,
+    current_class: ClassType,
+    diagnostics: Diagnostics,
+    definitions: Vec<Definition>,
+    scope_definitions: Vec<HashMap<Symbol, usize>>, // indexes into `definitions`, parallel to `scopes`
+    global_definitions: HashMap<Symbol, usize>,
+    global_references: Vec<(Symbol, Span)>, // linked up at the end, globals may be declared later
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![],
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            diagnostics: Diagnostics::new(),
+            definitions: vec![],
+            scope_definitions: vec![],
+            global_definitions: HashMap::new(),
+            global_references: vec![],
+        }
+    }
+
+    // Starts inside already-running local scopes, outermost first, so an
+    // expression can be resolved against a paused program's environments.
+    pub fn with_scopes(scopes: Vec<HashMap<Symbol, bool>>) -> Self {
+        let defines = |name: &str| scopes.iter().any(|s| s.contains_key(&Symbol::intern(name)));
+        let current_class = if defines("super") {
+            ClassType::Subclass
+        } else if defines("this") {
+            ClassType::Class
+        } else {
+            ClassType::None
+        };
+
+        Self {
+            scope_definitions: vec![HashMap::new(); scopes.len()],
+            scopes,
+            current_function: FunctionType::None,
+            current_class,
+            diagnostics: Diagnostics::new(),
+            definitions: vec![],
+            global_definitions: HashMap::new(),
+            global_references: vec![],
+        }
+    }
+
+    pub fn take_diagnostics(&mut self) -> Diagnostics {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    pub fn resolve(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.resolve_stmt(statement);
+        }
+    }
+
+    // every name declared so far, with the references found to each
+    pub fn take_definitions(&mut self) -> Vec<Definition> {
+        for (name, span) in std::mem::take(&mut self.global_references) {
+            if let Some(&index) = self.global_definitions.get(&name) {
+                self.definitions[index].references.push(span);
+            }
+        }
+        self.global_definitions.clear();
+        std::mem::take(&mut self.definitions)
+    }
+
+    pub fn resolve_expression(&mut self, expr: &Expr) {
+        self.resolve_expr(expr);
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block { statements, .. } => {
+                self.begin_scope();
+                self.resolve(statements);
+                self.end_scope();
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                span,
+            } => self.resolve_class(name, superclass.as_ref(), methods, *span),
+            Stmt::Expression { expression, .. } | Stmt::Print { expression, .. } => {
+                self.resolve_expr(expression)
+            }
+            Stmt::Function(declaration) => {
+                self.declare(&declaration.name);
+                self.add_definition(
+                    &declaration.name,
+                    DefinitionKind::Function,
+                    declaration.span,
+                    format!("fun {}", signature(declaration)),
+                );
+                self.define(&declaration.name);
+                self.resolve_function(declaration, FunctionType::Function);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            }
+            Stmt::Return { keyword, value, .. } => {
+                if self.current_function == FunctionType::None {
+                    self.error(
+                        keyword.span(),
+                        String::from("Can't return from top-level code."),
+                    );
+                }
+
+                if let Some(value) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error(
+                            keyword.span(),
+                            String::from("Can't return a value from an initializer."),
+                        );
+                    }
+                    self.resolve_expr(value);
+                }
+            }
+            Stmt::Var {
+                name,
+                initializer,
+                span,
+            } => {
+                self.declare(name);
+                self.add_definition(
+                    name,
+                    DefinitionKind::Variable,
+                    *span,
+                    format!("var {}", name.lexeme()),
+                );
+                if let Some(initializer) = initializer {
+                    self.resolve_expr(initializer);
+                }
+                self.define(name);
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(body);
+            }
+        }
+    }
+
+    fn resolve_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Rc<FunctionDeclaration>],
+        span: Span,
+    ) {
+        let enclosing_class = self.current_class;
+        self.current_class = ClassType::Class;
+
+        self.declare(name);
+        let detail = match superclass {
+            Some(Expr::Variable { token, .. }) => {
+                format!("class {} < {}", name.lexeme(), token.lexeme())
+            }
+            _ => format!("class {}", name.lexeme()),
+        };
+        self.add_definition(name, DefinitionKind::Class, span, detail);
+        self.define(name);
+
+        if let Some(superclass) = superclass {
+            if let Expr::Variable { token, .. } = superclass {
+                if token.lexeme() == name.lexeme() {
+                    self.error(
+                        token.span(),
+                        String::from("A class can't inherit from itself."),
+                    );
+                }
+            }
+
+            self.current_class = ClassType::Subclass;
+            self.resolve_expr(superclass);
+
+            self.begin_scope();
+            self.define_name(Symbol::intern("super"));
+        }
+
+        self.begin_scope();
+        self.define_name(Symbol::intern("this"));
+
+        for method in methods {
+            // methods are looked up on instances at runtime, so nothing refers to them by name
+            self.definitions.push(Definition {
+                name: method.name.symbol().clone(),
+                kind: DefinitionKind::Method,
+                span: method.name.span(),
+                declaration: method.span,
+                detail: format!("{}.{}", name.lexeme(), signature(method)),
+                container: Some(name.symbol().clone()),
+                references: vec![],
+            });
+
+            let function_type = if method.name.lexeme() == "init" {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.resolve_function(method, function_type);
+        }
+
+        self.end_scope();
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.current_class = enclosing_class;
+    }
+
+    fn resolve_function(&mut self, declaration: &FunctionDeclaration, function_type: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+            self.add_definition(
+                param,
+                DefinitionKind::Parameter,
+                param.span(),
+                format!("(parameter) {}", param.lexeme()),
+            );
+            self.define(param);
+        }
+        self.resolve(&declaration.body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, value, depth } => {
+                self.resolve_expr(value);
+                self.resolve_local(name, depth);
+            }
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.resolve_expr(callee);
+                for argument in arguments {
+                    self.resolve_expr(argument);
+                }
+            }
+            Expr::Get { object, .. } => self.resolve_expr(object),
+            Expr::Set { object, value, .. } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            Expr::Index { object, index, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            Expr::List { elements, .. } => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            Expr::Super { keyword, depth, .. } => {
+                match self.current_class {
+                    ClassType::None => self.error(
+                        keyword.span(),
+                        String::from("Can't use 'super' outside of a class."),
+                    ),
+                    ClassType::Class => self.error(
+                        keyword.span(),
+                        String::from("Can't use 'super' in a class with no superclass."),
+                    ),
+                    ClassType::Subclass => {}
+                }
+                self.resolve_local(keyword, depth);
+            }
+            Expr::This { keyword, depth } => {
+                if self.current_class == ClassType::None {
+                    self.error(
+                        keyword.span(),
+                        String::from("Can't use 'this' outside of a class."),
+                    );
+                }
+                self.resolve_local(keyword, depth);
+            }
+            Expr::Conditional {
+                condition,
+                consequent,
+                alternative,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_expr(consequent);
+                self.resolve_expr(alternative);
+            }
+            Expr::Grouping { expression } => self.resolve_expr(expression),
+            Expr::Literal { .. } => {}
+            Expr::Unary { right, .. } => self.resolve_expr(right),
+            Expr::Variable { token, depth } => {
+                if let Some(scope) = self.scopes.last() {
+                    if scope.get(token.symbol()) == Some(&false) {
+                        self.error(
+                            token.span(),
+                            String::from("Can't read local variable in its own initializer."),
+                        );
+                    }
+                }
+                self.resolve_local(token, depth);
+            }
+        }
+    }
+
+    // searches the scopes innermost first; names not found anywhere are left as globals
+    fn resolve_local(&mut self, name: &Token, depth: &Cell<Option<usize>>) {
+        for (i, scope) in self.scopes.iter().rev().enumerate() {
+            if scope.contains_key(name.symbol()) {
+                depth.set(Some(i));
+                let definitions = &self.scope_definitions[self.scopes.len() - 1 - i];
+                if let Some(&index) = definitions.get(name.symbol()) {
+                    self.definitions[index].references.push(name.span());
+                }
+                return;
+            }
+        }
+        self.global_references
+            .push((name.symbol().clone(), name.span()));
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.diagnostics
+            .error(DiagnosticKind::Resolve, span, message);
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+        self.scope_definitions.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+        self.scope_definitions.pop();
+    }
+
+    fn add_definition(
+        &mut self,
+        name: &Token,
+        kind: DefinitionKind,
+        declaration: Span,
+        detail: String,
+    ) {
+        let index = self.definitions.len();
+        self.definitions.push(Definition {
+            name: name.symbol().clone(),
+            kind,
+            span: name.span(),
+            declaration,
+            detail,
+            container: None,
+            references: vec![],
+        });
+
+        let definitions = match self.scope_definitions.last_mut() {
+            Some(definitions) => definitions,
+            None => &mut self.global_definitions,
+        };
+        definitions.insert(name.symbol().clone(), index);
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        if scope.insert(name.symbol().clone(), false).is_some() {
+            self.error(
+                name.span(),
+                String::from("Already a variable with this name in this scope."),
+            );
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        self.define_name(name.symbol().clone());
+    }
+
+    fn define_name(&mut self, name: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, true);
+        }
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// `name(a, b)`
+fn signature(declaration: &FunctionDeclaration) -> String {
+    let params: Vec<&str> = declaration.params.iter().map(|p| p.lexeme()).collect();
+    format!("{}({})", declaration.name.lexeme(), params.join(", "))
+}
@@ -1,71 +1,420 @@
-use std::{
-    fmt::Display,
-    fs,
-    io::{self, Write},
-    process,
+use std::{fs, process, time::Instant};
+
+use rust_lox::{
+    ast_printable::ASTPrintable,
+    bytecode::heap::GcConfig,
+    debugger::Debugger,
+    errors::Diagnostics,
+    formatter,
+    frontend::{self, Parse},
+    linter::{self, LintConfig},
+    scanner::KEYWORDS,
+    Interpreter, LoxError, Vm,
 };
 
-use crate::{environment::Environment, errors, interpretable::Interpretable, parser, scanner};
+use crate::{
+    debug_prompt::DebugPrompt,
+    line_editor::{Input, LineEditor},
+    STACK_SIZE,
+};
 
-pub fn run_file(path: String) -> Result<(), RunnerError> {
-    let data = fs::read_to_string(path);
+// which engine runs the program; both share the same front end and output
+#[derive(Clone, Copy, Default)]
+pub enum Backend {
+    #[default]
+    TreeWalker,
+    Vm,
+}
 
-    match data {
-        Ok(script) => Ok(run(script)),
-        Err(_) => Err(RunnerError::FailedToRunFile),
-    }
+// what to print instead of running the program
+#[derive(Clone, Copy)]
+pub enum Dump {
+    Tokens,
+    Ast,
+    Bytecode,
 }
 
-pub fn run_prompt() {
-    println!("Starting REPL...");
-    let stdin = io::stdin();
-    let input = &mut String::new();
+// how the binary was asked to run, collected from the command line
+#[derive(Clone, Copy, Default)]
+pub struct Options {
+    pub backend: Backend,
+    pub dump: Option<Dump>,
+    pub gc: GcConfig,
+    pub lint: LintConfig,
+}
 
-    loop {
-        print!("> ");
-        let _ = io::stdout().flush();
-        match stdin.read_line(input) {
-            Ok(_) => run(input.clone()),
-            Err(_) => break,
+enum Session {
+    TreeWalker(Interpreter),
+    Vm(Vm),
+    Dump(Dump, Vm),
+}
+
+impl Session {
+    fn new(options: Options) -> Self {
+        match (options.backend, options.dump) {
+            (_, Some(dump)) => Session::Dump(dump, Vm::with_gc_config(options.gc)),
+            (Backend::TreeWalker, None) => {
+                let mut interpreter = interpreter(options.gc);
+                interpreter.set_lint_config(options.lint);
+                Session::TreeWalker(interpreter)
+            }
+            (Backend::Vm, None) => {
+                let mut vm = Vm::with_gc_config(options.gc);
+                vm.set_lint_config(options.lint);
+                Session::Vm(vm)
+            }
+        }
+    }
+
+    fn eval(&mut self, source: &str) -> Result<(), LoxError> {
+        match self {
+            Session::TreeWalker(interpreter) => interpreter.eval(source).map(|_| ()),
+            Session::Vm(vm) => vm.eval(source),
+            Session::Dump(dump, vm) => print_dump(*dump, vm, source, frontend::parse),
+        }
+    }
+
+    // evaluates a lone expression and returns its value the way `print`
+    // would show it
+    fn evaluate(&mut self, expression: &str) -> Result<Option<String>, LoxError> {
+        match self {
+            Session::TreeWalker(interpreter) => interpreter
+                .eval_expression(expression)
+                .map(|value| Some(value.to_string())),
+            Session::Vm(vm) => vm.eval_expression(expression).map(Some),
+            Session::Dump(dump, vm) => {
+                print_dump(*dump, vm, expression, frontend::parse_expression).map(|_| None)
+            }
         }
-        input.clear();
+    }
+
+    // runs one REPL entry, echoing its value when it is a lone expression
+    fn eval_entry(&mut self, entry: &str) -> Result<(), LoxError> {
+        let Some(expression) = lone_expression(entry) else {
+            self.eval(entry)?;
+            print_warnings(self.warnings());
+            return Ok(());
+        };
+        let value = self.evaluate(expression)?;
+        print_warnings(self.warnings());
+        if let Some(value) = value {
+            println!("{}", value);
+        }
+        Ok(())
+    }
+
+    // what the linter found in the source last run
+    fn warnings(&self) -> &Diagnostics {
+        match self {
+            Session::TreeWalker(interpreter) => interpreter.warnings(),
+            Session::Vm(vm) | Session::Dump(_, vm) => vm.warnings(),
+        }
+    }
+
+    // the globals and their values, sorted by name
+    fn globals(&self) -> Vec<(String, String)> {
+        match self {
+            Session::TreeWalker(interpreter) => interpreter
+                .globals()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            Session::Vm(vm) | Session::Dump(_, vm) => vm
+                .globals()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        }
+    }
+
+    // a script that fails has its warnings reported along with the error
+    fn run_file(&mut self, path: &str) -> Result<(), LoxError> {
+        match self {
+            Session::TreeWalker(interpreter) => interpreter.run_file(path).map(|_| ())?,
+            Session::Vm(vm) => vm.run_file(path)?,
+            Session::Dump(..) => {
+                let source = fs::read_to_string(path)?;
+                self.eval(&source)?
+            }
+        }
+        print_warnings(self.warnings());
+        Ok(())
     }
 }
 
-fn run(script: String) {
-    let mut scanner = scanner::Scanner::new(script);
-    let tokens = scanner.scan_tokens();
-    let mut parser = parser::Parser::new(tokens.clone());
-    let expressions = parser.parse();
-    let mut environment = Environment::new();
+fn print_dump(dump: Dump, vm: &mut Vm, source: &str, parse: Parse) -> Result<(), LoxError> {
+    match dump {
+        Dump::Tokens => print_tokens(source),
+        Dump::Ast => print_ast(source, parse),
+        Dump::Bytecode => {
+            print!("{}", vm.disassemble(source, parse)?);
+            Ok(())
+        }
+    }
+}
 
-    if errors::had_error() {
-        process::exit(65);
+// The dumps print as much as could be produced, then fail if the front end
+// reported errors.
+fn print_tokens(source: &str) -> Result<(), LoxError> {
+    let mut diagnostics = Diagnostics::with_source(source);
+    for token in frontend::scan(source, &mut diagnostics) {
+        let span = token.span();
+        let location = format!("{}:{}", span.line, span.column);
+        let token_type = format!("{:?}", token.type_info());
+        println!("{:<8} {:<24} {}", location, token_type, token);
     }
-    if errors::had_runtime_error() {
-        process::exit(70);
+    compile_result(diagnostics)
+}
+
+fn print_ast(source: &str, parse: Parse) -> Result<(), LoxError> {
+    let mut diagnostics = Diagnostics::with_source(source);
+    for statement in parse(source, LintConfig::default(), &mut diagnostics) {
+        println!("{}", statement.print());
+    }
+    compile_result(diagnostics)
+}
+
+fn compile_result(diagnostics: Diagnostics) -> Result<(), LoxError> {
+    if diagnostics.has_errors() {
+        return Err(LoxError::Compile(diagnostics));
+    }
+    Ok(())
+}
+
+pub fn run_file(path: &str, options: Options) {
+    let mut session = Session::new(options);
+    if let Err(e) = session.run_file(path) {
+        exit_with(e);
     }
+}
+
+// A tree-walker free to use half of the binary's stack, which lets calls nest
+// as deeply as in the VM even without optimizations.
+pub fn interpreter(gc: GcConfig) -> Interpreter {
+    let mut interpreter = Interpreter::with_gc_config(gc);
+    interpreter.set_stack_limit(STACK_SIZE / 2);
+    interpreter
+}
+
+// runs a script on the tree-walker, pausing before its first statement
+pub fn debug_file(path: &str) {
+    let mut interpreter = interpreter(GcConfig::default());
+    interpreter.attach_debugger(Debugger::new(Box::<DebugPrompt>::default()));
 
-    match expressions {
-        Some(exprs) => {
-            for expr in exprs {
-                if let Err(e) = expr.interpret() {
-                    errors::runtime_error(&e)
+    if let Err(e) = interpreter.run_file(path) {
+        exit_with(e);
+    }
+}
+
+// Reports every warning and error in each script without running it. Fails
+// when anything was found, so scripts can be checked before they ship.
+pub fn lint_files(paths: &[&String], config: LintConfig) {
+    let mut failed = false;
+
+    for path in paths {
+        match fs::read_to_string(path) {
+            Ok(source) => {
+                let diagnostics = linter::lint(&source, config);
+                if !diagnostics.is_empty() {
+                    eprintln!("{}:\n{}", path, diagnostics);
+                    failed = true;
                 }
             }
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                failed = true;
+            }
         }
-        None => (),
+    }
+
+    if failed {
+        process::exit(1);
     }
 }
 
-pub enum RunnerError {
-    FailedToRunFile,
+fn print_warnings(diagnostics: &Diagnostics) {
+    if !diagnostics.is_empty() && !diagnostics.has_errors() {
+        eprintln!("{}", diagnostics);
+    }
 }
 
-impl Display for RunnerError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::FailedToRunFile => write!(f, "Failed to run file!"),
+// Rewrites each script in the canonical layout. With `check` the files are
+// left alone and the run fails if any of them would change.
+pub fn format_files(paths: &[&String], check: bool) {
+    let mut failed = false;
+
+    for path in paths {
+        let result = fs::read_to_string(path)
+            .map_err(LoxError::from)
+            .and_then(|source| Ok((formatter::format(&source)?, source)));
+        let (formatted, source) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                failed = true;
+                continue;
+            }
+        };
+
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("Would reformat {}", path);
+            failed = true;
+        } else if let Err(e) = fs::write(path, formatted) {
+            eprintln!("{}: {}", path, e);
+            failed = true;
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn exit_with(e: LoxError) -> ! {
+    eprintln!("{}", e);
+    match e {
+        LoxError::Compile(_) => process::exit(65),
+        LoxError::Runtime(_) => process::exit(70),
+        LoxError::Io(_) => process::exit(99),
+    }
+}
+
+// the expression `entry` consists of, whether or not it was given its
+// semicolon
+fn lone_expression(entry: &str) -> Option<&str> {
+    let trimmed = entry.trim_end();
+    let expression = trimmed.strip_suffix(';').unwrap_or(trimmed);
+    frontend::is_expression(expression).then_some(expression)
+}
+
+// The commands the REPL understands besides Lox itself.
+const COMMANDS: [(&str, &str); 8] = [
+    (":load <file>", "run a script in this session"),
+    (":env", "list the globals and their values"),
+    (
+        ":ast <source>",
+        "show the syntax tree of the source without running it",
+    ),
+    (":tokens <source>", "show the tokens of the source"),
+    (":time <source>", "run the source and show how long it took"),
+    (":reset", "forget everything defined so far"),
+    (":help", "list these commands"),
+    (":quit", "leave the REPL"),
+];
+
+// Runs a command typed at the prompt. Returns false once the REPL should end.
+fn run_command(line: &str, session: &mut Session, options: Options) -> bool {
+    let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let argument = argument.trim();
+    let usage = COMMANDS
+        .iter()
+        .map(|(usage, _)| *usage)
+        .find(|usage| usage.split(' ').next() == Some(command));
+    if let Some(usage) = usage.filter(|usage| usage.contains(' ') && argument.is_empty()) {
+        eprintln!("Usage: {}", usage);
+        return true;
+    }
+
+    let result = match command {
+        ":load" => session.run_file(argument),
+        ":env" => {
+            for (name, value) in session.globals() {
+                println!("{} = {}", name, value);
+            }
+            Ok(())
+        }
+        ":ast" => match lone_expression(argument) {
+            Some(expression) => print_ast(expression, frontend::parse_expression),
+            None => print_ast(argument, frontend::parse),
+        },
+        ":tokens" => print_tokens(argument),
+        ":time" => {
+            let start = Instant::now();
+            let result = session.eval_entry(argument);
+            println!("Took {:?}", start.elapsed());
+            result
+        }
+        ":reset" => {
+            *session = Session::new(options);
+            Ok(())
+        }
+        ":help" => {
+            for (usage, description) in COMMANDS {
+                println!("{:<18} {}", usage, description);
+            }
+            Ok(())
+        }
+        ":quit" => return false,
+        _ => {
+            eprintln!("Unknown command '{}', see :help", command);
+            Ok(())
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+    }
+    true
+}
+
+// what Tab can complete: commands, keywords and the names defined so far
+fn completions(session: &Session) -> Vec<String> {
+    let commands = COMMANDS
+        .iter()
+        .map(|(usage, _)| usage.split(' ').next().unwrap_or(usage));
+    let keywords = KEYWORDS.iter().map(|(keyword, _)| *keyword);
+    commands
+        .chain(keywords)
+        .map(String::from)
+        .chain(session.globals().into_iter().map(|(name, _)| name))
+        .collect()
+}
+
+// Reads entries at the prompt until input ends, all run in the one session
+// so definitions carry over. An entry carries on over as many lines as it
+// takes to close its braces and parentheses, and Ctrl-C throws away whatever
+// has been typed of it so far. Entries that are a single expression have
+// their value printed, and lines starting with `:` are commands.
+pub fn run_prompt(options: Options) {
+    println!("Starting REPL...");
+    let mut editor = LineEditor::new();
+    let mut session = Session::new(options);
+    let mut entry = String::new();
+
+    loop {
+        let prompt = if entry.is_empty() { "> " } else { "... " };
+        editor.set_completions(completions(&session));
+        match editor.read_line(prompt) {
+            Input::Eof => break,
+            Input::Interrupted => entry.clear(),
+            Input::Line(line) => {
+                editor.add_history(&line);
+                if entry.is_empty() && line.trim_start().starts_with(':') {
+                    if !run_command(line.trim(), &mut session, options) {
+                        return;
+                    }
+                    continue;
+                }
+                entry.push_str(&line);
+                entry.push('\n');
+                if frontend::is_incomplete(&entry) {
+                    continue;
+                }
+                if let Err(e) = session.eval_entry(&entry) {
+                    eprintln!("{}", e);
+                }
+                entry.clear();
+            }
+        }
+    }
+
+    // an unfinished entry still gets its errors reported
+    if !entry.trim().is_empty() {
+        if let Err(e) = session.eval_entry(&entry) {
+            eprintln!("{}", e);
         }
     }
 }
@@ -0,0 +1,150 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    bytecode::{heap::GcConfig, vm::FRAMES_MAX},
+    collector::Collector,
+    debugger::{Debugger, OutputSink},
+    environment::Environment,
+    errors::{RuntimeError, RuntimeErrorType},
+    lox_value::LoxValue,
+    token::Span,
+};
+
+// Calls nest as deeply as they can in the VM, whose frames include one for
+// the script itself.
+const MAX_CALL_DEPTH: usize = FRAMES_MAX - 1;
+
+// How much native stack calls may take unless the embedder says otherwise:
+// half of what threads Rust spawns get by default, leaving the rest for the
+// embedder's own frames and for the call that finds the limit reached.
+pub const DEFAULT_STACK_LIMIT: usize = 1024 * 1024;
+
+// What a tree-walking interpreter keeps besides its environments. It is
+// passed down alongside the environment, so two interpreters never see each
+// other's debugger, output or objects.
+pub struct Runtime {
+    debugger: Option<Debugger>,
+    // the attached debugger's, kept apart from it so it stays in place while
+    // the debugger itself is busy with a paused program
+    debugger_output: Option<OutputSink>,
+    output: Option<OutputSink>, // the embedder's, used when there is no debugger's
+    call_depth: usize,          // calls in progress
+    stack_start: usize,         // where the native stack was when the run started
+    stack_limit: usize,         // how far calls may grow it from there, in bytes
+    collector: Collector,
+    source: Rc<str>, // the code running now, which functions declared in it keep
+}
+
+impl Runtime {
+    pub(crate) fn new(gc: GcConfig) -> Self {
+        Self {
+            debugger: None,
+            debugger_output: None,
+            output: None,
+            call_depth: 0,
+            stack_start: stack_address(),
+            stack_limit: DEFAULT_STACK_LIMIT,
+            collector: Collector::new(gc),
+            source: Rc::from(""),
+        }
+    }
+
+    pub(crate) fn attach_debugger(&mut self, mut debugger: Debugger) {
+        self.debugger_output = debugger.take_output();
+        self.debugger = Some(debugger);
+    }
+
+    pub(crate) fn detach_debugger(&mut self) -> Option<Debugger> {
+        let mut debugger = self.debugger.take()?;
+        debugger.restore_output(self.debugger_output.take());
+        Some(debugger)
+    }
+
+    pub(crate) fn redirect_output(&mut self, output: OutputSink) {
+        self.output = Some(output);
+    }
+
+    pub(crate) fn debugger(&mut self) -> Option<&mut Debugger> {
+        self.debugger.as_mut()
+    }
+
+    // measures the stack calls use from the caller's frame on
+    pub(crate) fn start_stack(&mut self) {
+        self.stack_start = stack_address();
+    }
+
+    pub(crate) fn set_stack_limit(&mut self, bytes: usize) {
+        self.stack_limit = bytes;
+    }
+
+    pub(crate) fn source(&self) -> &Rc<str> {
+        &self.source
+    }
+
+    // switches to running code from `source`, returning the code that was running
+    pub(crate) fn replace_source(&mut self, source: Rc<str>) -> Rc<str> {
+        std::mem::replace(&mut self.source, source)
+    }
+
+    // every environment, function, class, instance, list and map is created
+    // through this, so their cycles can be collected
+    pub(crate) fn collector(&mut self) -> &mut Collector {
+        &mut self.collector
+    }
+
+    // Called before every statement runs. The debugger is taken out while it
+    // works, so anything evaluated from a paused frame runs without stopping.
+    pub(crate) fn on_statement(&mut self, span: Span, environment: &Rc<RefCell<Environment>>) {
+        let Some(mut debugger) = self.debugger.take() else {
+            return;
+        };
+        debugger.statement(span, environment, self);
+        self.debugger = Some(debugger);
+    }
+
+    // writes a line the program printed, wherever the attached debugger or
+    // the embedder wants it
+    pub(crate) fn print(&mut self, text: &str) {
+        match self.debugger_output.as_mut().or(self.output.as_mut()) {
+            Some(output) => output(text),
+            None => println!("{}", text),
+        }
+    }
+
+    // Counts a call about to run `callee`, failing instead once there are too
+    // many or they have taken as much of the native stack as they may.
+    pub(crate) fn enter_call(&mut self, callee: &LoxValue, span: Span) -> Result<(), RuntimeError> {
+        let stack_used = stack_address().abs_diff(self.stack_start);
+        if self.call_depth == MAX_CALL_DEPTH || stack_used > self.stack_limit {
+            return Err(RuntimeError::new(
+                span,
+                callee.to_string(),
+                RuntimeErrorType::StackOverflow,
+            ));
+        }
+        self.call_depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn exit_call(&mut self) {
+        self.call_depth -= 1;
+    }
+
+    pub(crate) fn enter_function(&mut self, name: &str, environment: &Rc<RefCell<Environment>>) {
+        if let Some(debugger) = &mut self.debugger {
+            debugger.enter_function(name, environment);
+        }
+    }
+
+    pub(crate) fn exit_function(&mut self) {
+        if let Some(debugger) = &mut self.debugger {
+            debugger.exit_function();
+        }
+    }
+}
+
+// an address in the caller's stack frame, to tell how deep the stack is
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
@@ -1,38 +1,49 @@
 use std::collections::HashMap;
 
 use crate::{
-    errors,
-    token::{Token, TokenType},
+    errors::{DiagnosticKind, Diagnostics},
+    symbol::Symbol,
+    token::{Span, Token, TokenType},
 };
 
 pub struct Scanner {
-    source: Vec<char>,  // source code as a vector of characters
-    tokens: Vec<Token>, // vector of tokens produced
-    start: usize,       // first character in lexeme being scanned
-    current: usize,     // current character being scanned
-    line: u32,          // which line in source code we are scanning
+    source: Vec<char>,     // source code as a vector of characters
+    tokens: Vec<Token>,    // vector of tokens produced
+    start: usize,          // first character in lexeme being scanned
+    current: usize,        // current character being scanned
+    line: u32,             // which line in source code we are scanning
+    line_start: usize,     // first character of the line being scanned
+    current_offset: usize, // byte offset of the current character
+    start_span: Span,      // where the lexeme being scanned begins
     reserved_keywords: HashMap<&'static str, TokenType>, // keywords reserved by Lox
+    diagnostics: Diagnostics, // errors found while scanning
+    comments: Vec<Span>,   // comments skipped over, kept for the formatter
+    unterminated: bool,    // whether the source ended inside a string or block comment
 }
 
+// Words that can't be used as identifiers.
+pub const KEYWORDS: [(&str, TokenType); 16] = [
+    ("and", TokenType::And),
+    ("class", TokenType::Class),
+    ("else", TokenType::Else),
+    ("false", TokenType::False),
+    ("for", TokenType::For),
+    ("fun", TokenType::Fun),
+    ("if", TokenType::If),
+    ("nil", TokenType::Nil),
+    ("or", TokenType::Or),
+    ("print", TokenType::Print),
+    ("return", TokenType::Return),
+    ("super", TokenType::Super),
+    ("this", TokenType::This),
+    ("true", TokenType::True),
+    ("var", TokenType::Var),
+    ("while", TokenType::While),
+];
+
 impl Scanner {
     pub fn new(source: String) -> Self {
-        let mut reserved: HashMap<&'static str, TokenType> = HashMap::new();
-        reserved.insert("and", TokenType::And);
-        reserved.insert("class", TokenType::Class);
-        reserved.insert("else", TokenType::Else);
-        reserved.insert("false", TokenType::False);
-        reserved.insert("for", TokenType::For);
-        reserved.insert("fun", TokenType::Fun);
-        reserved.insert("if", TokenType::If);
-        reserved.insert("nil", TokenType::Nil);
-        reserved.insert("or", TokenType::Or);
-        reserved.insert("print", TokenType::Print);
-        reserved.insert("return", TokenType::Return);
-        reserved.insert("super", TokenType::Super);
-        reserved.insert("this", TokenType::This);
-        reserved.insert("true", TokenType::True);
-        reserved.insert("var", TokenType::Var);
-        reserved.insert("while", TokenType::While);
+        let reserved = KEYWORDS.iter().cloned().collect();
 
         Self {
             source: source.chars().collect(),
@@ -40,23 +51,46 @@ impl Scanner {
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            current_offset: 0,
+            start_span: Span::default(),
             reserved_keywords: reserved,
+            diagnostics: Diagnostics::new(),
+            comments: Vec::new(),
+            unterminated: false,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> &Vec<Token> {
+    pub fn take_diagnostics(&mut self) -> Diagnostics {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    // where each comment sits, in source order, including nested block comments whole
+    pub fn take_comments(&mut self) -> Vec<Span> {
+        std::mem::take(&mut self.comments)
+    }
+
+    // whether the source ended partway through a string or block comment, which
+    // more source could still close
+    pub fn is_unterminated(&self) -> bool {
+        self.unterminated
+    }
+
+    // hands over the tokens, always ending with an end-of-file token
+    pub fn scan_tokens(&mut self) -> Vec<Token> {
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_span = Span::new(self.current_offset, 0, self.line, self.column());
             self.scan_token();
         }
 
         self.tokens.push(Token::new(
             TokenType::EndOfFile,
-            String::from(""),
-            self.line,
+            Symbol::intern(""),
+            Span::new(self.current_offset, 0, self.line, self.column()),
         ));
 
-        return &self.tokens;
+        std::mem::take(&mut self.tokens)
     }
 
     fn is_at_end(&self) -> bool {
@@ -71,6 +105,8 @@ impl Scanner {
             ')' => self.add_basic_token(TokenType::RightParen),
             '{' => self.add_basic_token(TokenType::LeftBrace),
             '}' => self.add_basic_token(TokenType::RightBrace),
+            '[' => self.add_basic_token(TokenType::LeftBracket),
+            ']' => self.add_basic_token(TokenType::RightBracket),
             ',' => self.add_basic_token(TokenType::Comma),
             '.' => self.add_basic_token(TokenType::Dot),
             '-' => self.add_basic_token(TokenType::Minus),
@@ -85,15 +121,14 @@ impl Scanner {
             '>' => self.parse_with_next_char(TokenType::Greater, TokenType::GreaterEqual, '='),
             '/' => self.parse_slash(),
             '"' => self.parse_string(),
-            '\n' => self.line += 1,
-            ' ' | '\r' | '\t' => {} // do nothing
+            ' ' | '\r' | '\t' | '\n' => {} // do nothing
             _ => {
-                if c.is_digit(10) {
+                if c.is_ascii_digit() {
                     self.parse_number();
                 } else if c.is_alphanumeric() {
                     self.parse_identifier()
                 } else {
-                    errors::error(self.line, String::from("Unexpected character."))
+                    self.error(String::from("Unexpected character."))
                 }
             }
         }
@@ -111,19 +146,19 @@ impl Scanner {
         if let Some(which) = self.reserved_keywords.get(identifier.as_str()) {
             self.add_token(which.clone());
         } else {
-            self.add_token(TokenType::Identifier(identifier));
+            self.add_token(TokenType::Identifier(Symbol::intern(&identifier)));
         }
     }
 
     fn parse_number(&mut self) {
-        while self.peek().is_digit(10) {
+        while self.peek().is_ascii_digit() {
             self.advance();
         }
 
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
 
-            while self.peek().is_digit(10) {
+            while self.peek().is_ascii_digit() {
                 self.advance();
             }
         }
@@ -136,20 +171,19 @@ impl Scanner {
         if let Ok(n) = n {
             self.add_token(TokenType::Number(n));
         } else {
-            errors::error(self.line, String::from("Unable to parse number."));
+            self.error(String::from("Unable to parse number."));
         }
     }
 
     fn parse_string(&mut self) {
         while !self.is_at_end() && self.peek() != '"' {
-            if self.peek() == '\n' {
-                self.line += 1;
-            }
             self.advance();
         }
 
         if self.is_at_end() {
-            errors::error(self.line, String::from("Unterminated string."))
+            self.unterminated = true;
+            self.error(String::from("Unterminated string."));
+            return;
         }
 
         // advance past closing quotation mark
@@ -160,17 +194,14 @@ impl Scanner {
             .iter()
             .collect::<String>();
 
-        self.add_token(TokenType::Str(text));
+        self.add_token(TokenType::Str(Symbol::intern(&text)));
     }
 
     fn parse_block_comment(&mut self) {
         // shed '*'
         self.advance();
 
-        while !self.is_at_end() && !(self.peek() == '*' && self.peek_next() == '/') {
-            if self.peek() == '\n' {
-                self.line += 1;
-            }
+        while !(self.is_at_end() || (self.peek() == '*' && self.peek_next() == '/')) {
             if self.peek() == '/' && self.peek_next() == '*' {
                 self.parse_block_comment();
             } else {
@@ -178,7 +209,9 @@ impl Scanner {
             }
         }
 
-        if !self.is_at_end() {
+        if self.is_at_end() {
+            self.unterminated = true;
+        } else {
             // shed '*'
             self.advance();
             // shed '/'
@@ -191,8 +224,10 @@ impl Scanner {
             while !self.is_at_end() && self.peek() != '\n' {
                 self.advance();
             }
+            self.comments.push(self.lexeme_span());
         } else if self.match_next('*') {
             self.parse_block_comment();
+            self.comments.push(self.lexeme_span());
         } else {
             self.add_basic_token(TokenType::Slash)
         }
@@ -236,10 +271,33 @@ impl Scanner {
         }
     }
 
+    // newlines are counted here so every path that consumes one keeps the line and column right
     fn advance(&mut self) -> char {
-        let c = self.source.get(self.current).unwrap();
+        let c = *self.source.get(self.current).unwrap();
         self.current += 1;
-        *c
+        self.current_offset += c.len_utf8();
+
+        if c == '\n' {
+            self.line += 1;
+            self.line_start = self.current;
+        }
+        c
+    }
+
+    fn column(&self) -> u32 {
+        (self.current - self.line_start + 1) as u32
+    }
+
+    fn lexeme_span(&self) -> Span {
+        Span {
+            length: self.current_offset - self.start_span.offset,
+            ..self.start_span
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        let span = self.lexeme_span();
+        self.diagnostics.error(DiagnosticKind::Scan, span, message);
     }
 
     fn add_basic_token(&mut self, which: TokenType) {
@@ -247,9 +305,16 @@ impl Scanner {
     }
 
     fn add_token(&mut self, which: TokenType) {
-        let text = self.source[self.start..self.current]
-            .iter()
-            .collect::<String>();
-        self.tokens.push(Token::new(which, text, self.line));
+        // identifiers already carry their interned text
+        let lexeme = match &which {
+            TokenType::Identifier(name) => name.clone(),
+            _ => Symbol::intern(
+                &self.source[self.start..self.current]
+                    .iter()
+                    .collect::<String>(),
+            ),
+        };
+        self.tokens
+            .push(Token::new(which, lexeme, self.lexeme_span()));
     }
 }
@@ -1,24 +1,236 @@
-use crate::{errors::RuntimeError, expr::Expr, interpretable::Interpretable, lox_value::LoxValue};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
+use crate::{
+    environment::Environment,
+    errors::{RuntimeError, RuntimeErrorType},
+    expr::Expr,
+    interpretable::Interpretable,
+    lox_class::LoxClass,
+    lox_function::LoxFunction,
+    lox_value::LoxValue,
+    runtime::Runtime,
+    symbol::Symbol,
+    token::{Span, Token},
+};
+
+pub struct FunctionDeclaration {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+// every statement records the span of source it was parsed from
 pub enum Stmt {
-    Expression(Expr),
-    Print(Expr),
-    Var { name: String, initializer: Expr },
+    Block {
+        statements: Vec<Stmt>,
+        span: Span,
+    },
+    Class {
+        name: Token,
+        superclass: Option<Expr>,
+        methods: Vec<Rc<FunctionDeclaration>>,
+        span: Span,
+    },
+    Expression {
+        expression: Expr,
+        span: Span,
+    },
+    Function(Rc<FunctionDeclaration>),
+    If {
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+        span: Span,
+    },
+    Print {
+        expression: Expr,
+        span: Span,
+    },
+    Return {
+        keyword: Token,
+        value: Option<Expr>,
+        span: Span,
+    },
+    Var {
+        name: Token,
+        initializer: Option<Expr>,
+        span: Span,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+        span: Span,
+    },
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Function(declaration) => declaration.span,
+            Stmt::Block { span, .. }
+            | Stmt::Class { span, .. }
+            | Stmt::Expression { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::Print { span, .. }
+            | Stmt::Return { span, .. }
+            | Stmt::Var { span, .. }
+            | Stmt::While { span, .. } => *span,
+        }
+    }
 }
 
 impl Interpretable for Stmt {
-    fn interpret(&self) -> Result<LoxValue, RuntimeError> {
+    fn interpret(
+        &self,
+        environment: &Rc<RefCell<Environment>>,
+        runtime: &mut Runtime,
+    ) -> Result<LoxValue, RuntimeError> {
+        runtime.on_statement(self.span(), environment);
+
         match self {
-            Stmt::Expression(expr) => expr.interpret(),
-            Stmt::Print(expr) => {
-                let value = expr.interpret()?;
-                println!("{}", value);
+            Stmt::Block { statements, .. } => {
+                let block_environment = runtime
+                    .collector()
+                    .environment(Environment::with_enclosing(Rc::clone(environment)));
+                for statement in statements {
+                    statement.interpret(&block_environment, runtime)?;
+                }
+                Ok(LoxValue::Nil)
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => interpret_class(name, superclass.as_ref(), methods, environment, runtime),
+            Stmt::Expression { expression, .. } => expression.interpret(environment, runtime),
+            Stmt::Function(declaration) => {
+                let function = LoxFunction::new(
+                    Rc::clone(declaration),
+                    Rc::clone(environment),
+                    false,
+                    Rc::clone(runtime.source()),
+                );
+                let function = runtime.collector().function(function);
+                environment.borrow_mut().define(
+                    declaration.name.symbol().clone(),
+                    LoxValue::Function(function),
+                );
+                Ok(LoxValue::Nil)
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                if condition.interpret(environment, runtime)?.is_truthy() {
+                    then_branch.interpret(environment, runtime)
+                } else if let Some(else_branch) = else_branch {
+                    else_branch.interpret(environment, runtime)
+                } else {
+                    Ok(LoxValue::Nil)
+                }
+            }
+            Stmt::Print { expression, .. } => {
+                let value = expression.interpret(environment, runtime)?;
+                runtime.print(&value.to_string());
                 Ok(value)
             }
+            Stmt::Return { keyword, value, .. } => {
+                let value = match value {
+                    Some(value) => value.interpret(environment, runtime)?,
+                    None => LoxValue::Nil,
+                };
+                Err(RuntimeError::new(
+                    keyword.span(),
+                    String::from(keyword.lexeme()),
+                    RuntimeErrorType::Return(value),
+                ))
+            }
             Stmt::Var {
-                name,
-                initializer: expr,
-            } => expr.interpret(),
+                name, initializer, ..
+            } => {
+                let value = match initializer {
+                    Some(initializer) => initializer.interpret(environment, runtime)?,
+                    None => LoxValue::Nil,
+                };
+                environment
+                    .borrow_mut()
+                    .define(name.symbol().clone(), value.clone());
+                Ok(value)
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                while condition.interpret(environment, runtime)?.is_truthy() {
+                    body.interpret(environment, runtime)?;
+                }
+                Ok(LoxValue::Nil)
+            }
         }
     }
 }
+
+fn interpret_class(
+    name: &Token,
+    superclass: Option<&Expr>,
+    methods: &[Rc<FunctionDeclaration>],
+    environment: &Rc<RefCell<Environment>>,
+    runtime: &mut Runtime,
+) -> Result<LoxValue, RuntimeError> {
+    let superclass = match superclass {
+        Some(expr) => match expr.interpret(environment, runtime)? {
+            LoxValue::Class(class) => Some(class),
+            value => {
+                return Err(RuntimeError::new(
+                    expr.span(),
+                    value.to_string(),
+                    RuntimeErrorType::SuperclassMustBeClass,
+                ))
+            }
+        },
+        None => None,
+    };
+
+    environment
+        .borrow_mut()
+        .define(name.symbol().clone(), LoxValue::Nil);
+
+    // subclass methods close over an extra environment that binds `super`
+    let method_environment = match &superclass {
+        Some(superclass) => {
+            let super_environment = runtime
+                .collector()
+                .environment(Environment::with_enclosing(Rc::clone(environment)));
+            super_environment.borrow_mut().define(
+                Symbol::intern("super"),
+                LoxValue::Class(Rc::clone(superclass)),
+            );
+            super_environment
+        }
+        None => Rc::clone(environment),
+    };
+
+    let mut class_methods = HashMap::new();
+    for method in methods {
+        let function = LoxFunction::new(
+            Rc::clone(method),
+            Rc::clone(&method_environment),
+            method.name.lexeme() == "init",
+            Rc::clone(runtime.source()),
+        );
+        class_methods.insert(
+            method.name.symbol().clone(),
+            runtime.collector().function(function),
+        );
+    }
+
+    let class = LoxClass::new(String::from(name.lexeme()), superclass, class_methods);
+    let class = runtime.collector().class(class);
+    environment
+        .borrow_mut()
+        .assign(name, LoxValue::Class(class))?;
+    Ok(LoxValue::Nil)
+}
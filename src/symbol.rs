@@ -0,0 +1,96 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    ops::Deref,
+    rc::Rc,
+};
+
+// Strings the interner holds before it checks for ones nobody uses anymore.
+const INITIAL_PURGE_THRESHOLD: usize = 1024;
+
+// An interned string. Every symbol with the same text shares one allocation,
+// so comparing and hashing symbols never looks at their characters.
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+
+impl Symbol {
+    pub fn intern(text: &str) -> Symbol {
+        INTERNER.with(|interner| interner.borrow_mut().intern(text))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.0).cast::<u8>().hash(state);
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", &*self.0)
+    }
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+// Hands out the shared allocation for each distinct string. Strings built at
+// runtime are interned too, so once the table has doubled in size it drops
+// every string only it still refers to.
+struct Interner {
+    strings: HashSet<Rc<str>>,
+    purge_threshold: usize,
+}
+
+impl Interner {
+    fn new() -> Self {
+        Self {
+            strings: HashSet::new(),
+            purge_threshold: INITIAL_PURGE_THRESHOLD,
+        }
+    }
+
+    fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(existing) = self.strings.get(text) {
+            return Symbol(Rc::clone(existing));
+        }
+
+        if self.strings.len() >= self.purge_threshold {
+            self.strings.retain(|s| Rc::strong_count(s) > 1);
+            self.purge_threshold = (self.strings.len() * 2).max(INITIAL_PURGE_THRESHOLD);
+        }
+
+        let string: Rc<str> = Rc::from(text);
+        self.strings.insert(Rc::clone(&string));
+        Symbol(string)
+    }
+}
@@ -1,18 +1,60 @@
 use std::fmt::Display;
 
+use crate::symbol::Symbol;
+
+// Location of a piece of source code. `offset` and `length` are in bytes so
+// the text can be sliced straight out of the source, `column` counts
+// characters from the start of the line.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub offset: usize,
+    pub length: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Span {
+    pub fn new(offset: usize, length: usize, line: u32, column: u32) -> Self {
+        Self {
+            offset,
+            length,
+            line,
+            column,
+        }
+    }
+
+    pub fn end(&self) -> usize {
+        self.offset + self.length
+    }
+
+    // smallest span covering both `self` and `other`
+    pub fn to(&self, other: Span) -> Span {
+        let (start, end) = if other.offset < self.offset {
+            (other, *self)
+        } else {
+            (*self, other)
+        };
+
+        Span {
+            length: end.end().max(start.end()) - start.offset,
+            ..start
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     token_type: TokenType,
-    lexeme: String,
-    line: u32,
+    lexeme: Symbol,
+    span: Span,
 }
 
 impl Token {
-    pub fn new(which: TokenType, lexeme: String, line: u32) -> Self {
+    pub fn new(which: TokenType, lexeme: Symbol, span: Span) -> Self {
         Self {
             token_type: which,
             lexeme,
-            line,
+            span,
         }
     }
 
@@ -20,12 +62,21 @@ impl Token {
         &self.lexeme
     }
 
+    // the lexeme as an interned symbol, for use as a variable or property name
+    pub fn symbol(&self) -> &Symbol {
+        &self.lexeme
+    }
+
     pub fn type_info(&self) -> &TokenType {
         &self.token_type
     }
 
     pub fn line(&self) -> u32 {
-        self.line
+        self.span.line
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
     }
 }
 
@@ -36,6 +87,8 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -57,8 +110,8 @@ pub enum TokenType {
     Colon,
 
     // Literals
-    Identifier(String),
-    Str(String),
+    Identifier(Symbol),
+    Str(Symbol),
     Number(f64),
 
     // Keywords
@@ -0,0 +1,143 @@
+use std::{cell::RefCell, fs, path::Path, rc::Rc, thread};
+
+use rust_lox::{bytecode::heap::GcConfig, debugger::OutputSink, Interpreter, Vm};
+
+// Every script in tests/scripts runs through the tree-walker and the VM, with
+// and without a collection before each allocation, and all four have to print
+// the same thing and fail with the same error.
+//
+// A script's own expectations sit in its comments: `// expect: <line>` for
+// each line it prints, in order, and `// expect runtime error: <message>` on
+// the line a runtime error is reported at.
+
+#[test]
+fn backends_agree_on_every_script() {
+    let mut paths: Vec<_> =
+        fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts"))
+            .expect("reads tests/scripts")
+            .map(|entry| entry.expect("reads a directory entry").path())
+            .filter(|path| path.extension().is_some_and(|e| e == "lox"))
+            .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no scripts in tests/scripts");
+
+    for path in paths {
+        let source = fs::read_to_string(&path).expect("reads the script");
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let expected = expectations(&source);
+
+        let stress = GcConfig {
+            stress: true,
+            ..GcConfig::default()
+        };
+        let runs = [
+            ("tree-walker", run_interpreter(&source, GcConfig::default())),
+            ("tree-walker --gc-stress", run_interpreter(&source, stress)),
+            ("vm", run_vm(&source, GcConfig::default())),
+            ("vm --gc-stress", run_vm(&source, stress)),
+        ];
+        for (backend, run) in &runs {
+            assert_eq!(
+                run.printed, expected.printed,
+                "{}: {} printed the wrong lines",
+                name, backend
+            );
+            assert_eq!(
+                run.error.as_deref().and_then(reported),
+                expected.error,
+                "{}: {} failed differently: {:?}",
+                name,
+                backend,
+                run.error
+            );
+        }
+        // the rendered errors, snippets and all, match too
+        let (_, first) = &runs[0];
+        for (backend, run) in &runs[1..] {
+            assert_eq!(
+                run.error, first.error,
+                "{}: {} rendered its error differently",
+                name, backend
+            );
+        }
+    }
+}
+
+struct Run {
+    printed: Vec<String>,
+    error: Option<String>,
+}
+
+struct Expectations {
+    printed: Vec<String>,
+    error: Option<(usize, String)>, // the line it is reported at and its message
+}
+
+fn expectations(source: &str) -> Expectations {
+    let mut printed = vec![];
+    let mut error = None;
+    for (i, line) in source.lines().enumerate() {
+        if let Some((_, text)) = line.split_once("// expect: ") {
+            printed.push(text.to_string());
+        } else if let Some((_, message)) = line.split_once("// expect runtime error: ") {
+            error = Some((i + 1, message.to_string()));
+        }
+    }
+    Expectations { printed, error }
+}
+
+// the line and message of a rendered runtime error, whose report comes after
+// any warnings as `[line <line>:<column>] Runtime error: <message>`
+fn reported(error: &str) -> Option<(usize, String)> {
+    error.lines().find_map(|report| {
+        let (position, message) = report
+            .strip_prefix("[line ")?
+            .split_once("] Runtime error: ")?;
+        let (line, _) = position.split_once(':')?;
+        Some((line.parse().ok()?, message.to_string()))
+    })
+}
+
+// The tree-walker recurses on the native stack, so a script calling as deep as
+// the VM allows needs a lot more of it than a test thread has.
+fn on_large_stack<F: FnOnce() -> Run + Send + 'static>(run: F) -> Run {
+    thread::Builder::new()
+        .stack_size(256 * 1024 * 1024)
+        .spawn(run)
+        .expect("spawns the backend thread")
+        .join()
+        .expect("the backend thread panicked")
+}
+
+fn run_interpreter(source: &str, config: GcConfig) -> Run {
+    let source = source.to_string();
+    on_large_stack(move || {
+        let printed = Rc::new(RefCell::new(vec![]));
+        let mut interpreter = Interpreter::with_gc_config(config);
+        interpreter.set_stack_limit(128 * 1024 * 1024);
+        interpreter.redirect_output(capture(&printed));
+        let error = interpreter.eval(&source).err().map(|e| e.to_string());
+        finish(printed, error)
+    })
+}
+
+fn run_vm(source: &str, config: GcConfig) -> Run {
+    let source = source.to_string();
+    on_large_stack(move || {
+        let printed = Rc::new(RefCell::new(vec![]));
+        let mut vm = Vm::with_gc_config(config);
+        vm.redirect_output(capture(&printed));
+        let error = vm.eval(&source).err().map(|e| e.to_string());
+        finish(printed, error)
+    })
+}
+
+fn capture(printed: &Rc<RefCell<Vec<String>>>) -> OutputSink {
+    let printed = Rc::clone(printed);
+    Box::new(move |text| printed.borrow_mut().push(text.to_string()))
+}
+
+fn finish(printed: Rc<RefCell<Vec<String>>>, error: Option<String>) -> Run {
+    let printed = printed.borrow().clone();
+    Run { printed, error }
+}
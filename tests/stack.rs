@@ -0,0 +1,79 @@
+use std::thread;
+
+use rust_lox::{Interpreter, LoxError};
+
+const RECURSE: &str = "fun r(n) { if (n > 0) return 1 + r(n - 1); return 0; }";
+
+// Runs `source` on a thread of the default size, 2 MiB, the same as `cargo
+// test` gives each test, and returns its value or error as text.
+fn eval_on_default_thread(source: &'static str, stack_limit: Option<usize>) -> String {
+    thread::spawn(move || {
+        let mut interpreter = Interpreter::new();
+        if let Some(bytes) = stack_limit {
+            interpreter.set_stack_limit(bytes);
+        }
+        match interpreter.eval(source) {
+            Ok(value) => value.to_string(),
+            Err(e) => e.to_string(),
+        }
+    })
+    .join()
+    .expect("the interpreter thread panicked")
+}
+
+#[test]
+fn deep_recursion_is_a_runtime_error() {
+    let source = "fun r(n) { if (n > 0) return 1 + r(n - 1); return 0; } r(2000);";
+    let result = eval_on_default_thread(source, None);
+    assert!(result.contains("Stack overflow: <fn r>"), "{}", result);
+}
+
+#[test]
+fn deep_method_calls_are_a_runtime_error() {
+    let source = "class A { init(n) { if (n > 0) A(n - 1); } } A(2000);";
+    let result = eval_on_default_thread(source, None);
+    assert!(result.contains("Stack overflow: A"), "{}", result);
+}
+
+#[test]
+fn shallow_recursion_runs() {
+    let source = "fun r(n) { if (n > 0) return 1 + r(n - 1); return 0; } r(20);";
+    assert_eq!(eval_on_default_thread(source, None), "20");
+}
+
+#[test]
+fn stack_overflow_leaves_the_interpreter_usable() {
+    let result = thread::spawn(|| {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval(RECURSE).expect("defines r");
+        let overflow = interpreter.eval("r(100000);");
+        assert!(matches!(overflow, Err(LoxError::Runtime(_))));
+        interpreter.eval("r(10);").map(|value| value.to_string())
+    })
+    .join()
+    .expect("the interpreter thread panicked");
+    assert_eq!(result.ok().as_deref(), Some("10"));
+}
+
+#[test]
+fn a_larger_stack_allows_deeper_calls() {
+    let result = thread::Builder::new()
+        .stack_size(256 * 1024 * 1024)
+        .spawn(|| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_stack_limit(128 * 1024 * 1024);
+            let source = "fun r(n) { if (n > 0) return 1 + r(n - 1); return 0; } r(1022);";
+            interpreter.eval(source).map(|value| value.to_string())
+        })
+        .expect("spawns the interpreter thread")
+        .join()
+        .expect("the interpreter thread panicked");
+    assert_eq!(result.ok().as_deref(), Some("1022"));
+}
+
+#[test]
+fn a_lower_limit_stops_calls_sooner() {
+    let source = "fun r(n) { if (n > 0) return 1 + r(n - 1); return 0; } r(50);";
+    let result = eval_on_default_thread(source, Some(16 * 1024));
+    assert!(result.contains("Stack overflow"), "{}", result);
+}